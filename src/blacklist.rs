@@ -0,0 +1,102 @@
+use crate::hstr::{self, Entry, History};
+use crate::settings::Pattern;
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/* Commands left out of the history before it is ranked. Exact
+ * commands and patterns come from the config file, while the
+ * commands blacklisted from the interface are kept in a file
+ * of their own. */
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Blacklist {
+    pub commands: Vec<String>,
+    pub patterns: Vec<Pattern>,
+    #[serde(skip)]
+    pub entries: History,
+}
+
+impl Blacklist {
+    pub fn load(&mut self, path: &Path) -> anyhow::Result<()> {
+        /* Nothing has been blacklisted until the first command is. */
+        if !path.exists() {
+            return Ok(());
+        }
+        let bytes = fs::read(path)
+            .with_context(|| format!("Could not read blacklist file {}", path.display()))?;
+        self.entries = hstr::from_lines(&bytes);
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, hstr::to_lines(&self.entries))
+            .with_context(|| format!("Could not write blacklist file {}", path.display()))
+    }
+
+    pub fn add(&mut self, entry: &Entry) {
+        if !self.entries.contains(entry) {
+            self.entries.push(Entry::new(entry.bytes.clone()));
+        }
+    }
+
+    pub fn contains(&self, entry: &Entry) -> bool {
+        /* Patterns match anywhere in the command
+         * unless they are anchored. */
+        self.entries.contains(entry)
+            || self.commands.iter().any(|command| command == &entry.text)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.regex().is_match(&entry.text))
+    }
+}
+
+pub fn default_path() -> anyhow::Result<PathBuf> {
+    /* $XDG_DATA_HOME/hstr-rs/blacklist, or ~/.local/share/hstr-rs/blacklist */
+    dirs::data_dir()
+        .map(|dir| dir.join("hstr-rs").join("blacklist"))
+        .ok_or_else(|| anyhow!("Could not determine the data directory"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::convert::TryFrom;
+
+    fn fake_blacklist() -> Blacklist {
+        Blacklist {
+            commands: vec!["ls".into(), "cd ..".into()],
+            patterns: vec![Pattern::try_from("^cd ".to_string()).unwrap()],
+            entries: vec![Entry::from("git status")],
+        }
+    }
+
+    #[rstest(
+        command,
+        expected,
+        case("ls", true),
+        case("ls -la", false),
+        case("cd /tmp", true),
+        case("git status", true),
+        case("git push", false)
+    )]
+    fn contains(command: &str, expected: bool) {
+        assert_eq!(fake_blacklist().contains(&Entry::from(command)), expected);
+    }
+
+    #[test]
+    fn add() {
+        let mut blacklist = Blacklist::default();
+        let entry = Entry::from("clear");
+        blacklist.add(&entry);
+        blacklist.add(&entry);
+        assert!(blacklist.contains(&entry));
+        assert_eq!(blacklist.entries.len(), 1);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::hstr::{Entry, History};
+use crate::secrets::{self, Secrets};
+use crate::sort::Metadata;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Result, Write};
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Plain,
+    Json,
+    Jsonl,
+    Tsv,
+}
+
+impl Format {
+    pub fn from_str(string: &str) -> anyhow::Result<Self> {
+        match string {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(anyhow!("Unknown output format {}", string)),
+        }
+    }
+}
+
+/* One ranked entry, as documented in the README. Changes to
+ * this struct change the output schema. */
+#[derive(Debug, Serialize)]
+struct Record {
+    command: String,
+    count: usize,
+    last_position: usize,
+    first_seen: Option<i64>,
+    last_seen: Option<i64>,
+    secret: bool,
+}
+
+impl Record {
+    fn new(entry: &Entry, metadata: &Metadata, secrets: &[Range<usize>], mask: bool) -> Self {
+        /* `secrets` are where the secrets are in the command. */
+        let secret = !secrets.is_empty();
+        Self {
+            command: match secret && mask {
+                true => secrets::mask_ranges(&entry.text, secrets),
+                false => entry.text.clone(),
+            },
+            count: metadata.count,
+            last_position: metadata.last_position,
+            first_seen: metadata.first_seen,
+            last_seen: metadata.last_seen,
+            secret,
+        }
+    }
+}
+
+/* How commands with secrets in them are written out. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Redaction {
+    Show,
+    Mask,
+    Hide,
+}
+
+impl Redaction {
+    pub fn from_str(string: &str) -> anyhow::Result<Self> {
+        match string {
+            "show" => Ok(Redaction::Show),
+            "mask" => Ok(Redaction::Mask),
+            "hide" => Ok(Redaction::Hide),
+            _ => Err(anyhow!("Unknown redaction {}", string)),
+        }
+    }
+}
+
+pub fn write(
+    writer: &mut dyn Write,
+    history: &History,
+    metadata: &HashMap<Entry, Metadata>,
+    secrets: &Secrets,
+    redaction: Redaction,
+    format: Format,
+    separator: u8,
+) -> Result<()> {
+    /* Each command is searched for secrets only once. */
+    let history: Vec<(&Entry, Vec<Range<usize>>)> = history
+        .iter()
+        .map(|entry| (entry, secrets.find(&entry.text)))
+        .filter(|(_, found)| redaction != Redaction::Hide || found.is_empty())
+        .collect();
+    let mask = redaction == Redaction::Mask;
+    let records = history
+        .iter()
+        .map(|(entry, found)| Record::new(entry, &metadata[*entry], found, mask));
+
+    match format {
+        Format::Plain => records.zip(&history).try_for_each(|(record, (entry, _))| {
+            /* Commands are written as found in the history
+             * file unless they had to be masked. */
+            match record.secret && mask {
+                true => writer.write_all(record.command.as_bytes())?,
+                false => writer.write_all(&entry.bytes)?,
+            }
+            writer.write_all(&[separator])
+        }),
+        Format::Json => {
+            serde_json::to_writer(&mut *writer, &records.collect::<Vec<_>>())?;
+            writer.write_all(b"\n")
+        }
+        Format::Jsonl => records.into_iter().try_for_each(|record| {
+            serde_json::to_writer(&mut *writer, &record)?;
+            writer.write_all(b"\n")
+        }),
+        Format::Tsv => {
+            writer.write_all(b"command\tcount\tlast_position\tfirst_seen\tlast_seen\tsecret\n")?;
+            records.into_iter().try_for_each(|record| {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    escape_tsv(&record.command),
+                    record.count,
+                    record.last_position,
+                    optional(record.first_seen),
+                    optional(record.last_seen),
+                    record.secret,
+                )
+            })
+        }
+    }
+}
+
+fn escape_tsv(command: &str) -> String {
+    /* Tabs and newlines would break up the row. */
+    command
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn optional(value: Option<i64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort;
+    use rstest::rstest;
+
+    fn fake_history() -> History {
+        let mut history: History = vec!["make", "cat <<EOF\n\tspam\nEOF", "make"]
+            .into_iter()
+            .map(Entry::from)
+            .collect();
+        history[0].timestamp = Some(1330648651);
+        history[2].timestamp = Some(1330648653);
+        history
+    }
+
+    #[rstest(
+        format,
+        expected,
+        case(
+            Format::Json,
+            concat!(
+                r#"[{"command":"make","count":2,"last_position":2,"first_seen":1330648651,"last_seen":1330648653,"secret":false},"#,
+                r#"{"command":"cat <<EOF\n\tspam\nEOF","count":1,"last_position":1,"first_seen":null,"last_seen":null,"secret":false}]"#,
+                "\n"
+            )
+        ),
+        case(
+            Format::Jsonl,
+            concat!(
+                r#"{"command":"make","count":2,"last_position":2,"first_seen":1330648651,"last_seen":1330648653,"secret":false}"#,
+                "\n",
+                r#"{"command":"cat <<EOF\n\tspam\nEOF","count":1,"last_position":1,"first_seen":null,"last_seen":null,"secret":false}"#,
+                "\n"
+            )
+        ),
+        case(
+            Format::Tsv,
+            concat!(
+                "command\tcount\tlast_position\tfirst_seen\tlast_seen\tsecret\n",
+                "make\t2\t2\t1330648651\t1330648653\tfalse\n",
+                "cat <<EOF\\n\\tspam\\nEOF\t1\t1\t\t\tfalse\n"
+            )
+        ),
+        case(Format::Plain, "make\ncat <<EOF\n\tspam\nEOF\n")
+    )]
+    fn write(format: Format, expected: &str) {
+        let history = fake_history();
+        let metadata = sort::metadata_map(&history);
+        let ranked = sort::sort(history, sort::SortOrder::Frequency);
+        let mut output = Vec::new();
+        super::write(
+            &mut output,
+            &ranked,
+            &metadata,
+            &Secrets::default(),
+            Redaction::Show,
+            format,
+            b'\n',
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[rstest(
+        redaction,
+        expected,
+        case(Redaction::Show, "ls\nmysql -phunter2\n"),
+        case(Redaction::Mask, "ls\nmysql -p*******\n"),
+        case(Redaction::Hide, "ls\n")
+    )]
+    fn redacted(redaction: Redaction, expected: &str) {
+        let history: History = vec!["ls", "mysql -phunter2"]
+            .into_iter()
+            .map(Entry::from)
+            .collect();
+        let metadata = sort::metadata_map(&history);
+        let mut output = Vec::new();
+        super::write(
+            &mut output,
+            &history,
+            &metadata,
+            &Secrets::default(),
+            redaction,
+            Format::Plain,
+            b'\n',
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::hstr::{self, Entry, History};
+use anyhow::{anyhow, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Default)]
+pub struct Favorites {
+    pub entries: History,
+}
+
+impl Favorites {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        /* There are no favorites until the first one is added. */
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let bytes = fs::read(path)
+            .with_context(|| format!("Could not read favorites file {}", path.display()))?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_bytes())
+            .with_context(|| format!("Could not write favorites file {}", path.display()))
+    }
+
+    pub fn contains(&self, entry: &Entry) -> bool {
+        self.entries.contains(entry)
+    }
+
+    pub fn toggle(&mut self, entry: &Entry) {
+        /* Adds the entry if it isn't a favorite yet, removes it otherwise. */
+        if self.contains(entry) {
+            self.entries.retain(|favorite| favorite != entry);
+        } else {
+            self.entries.push(Entry::new(entry.bytes.clone()));
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            entries: hstr::from_lines(bytes),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        hstr::to_lines(&self.entries)
+    }
+}
+
+pub fn default_path() -> anyhow::Result<PathBuf> {
+    /* $XDG_DATA_HOME/hstr-rs/favorites, or ~/.local/share/hstr-rs/favorites */
+    dirs::data_dir()
+        .map(|dir| dir.join("hstr-rs").join("favorites"))
+        .ok_or_else(|| anyhow!("Could not determine the data directory"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggle() {
+        let mut favorites = Favorites::default();
+        let entry = Entry::from("kubectl rollout restart deployment/spam");
+        favorites.toggle(&entry);
+        assert!(favorites.contains(&entry));
+        favorites.toggle(&entry);
+        assert!(!favorites.contains(&entry));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut favorites = Favorites::default();
+        favorites.toggle(&Entry::from("cat <<EOF\nspam\nEOF"));
+        favorites.toggle(&Entry::from("printf '\\n'"));
+        favorites.toggle(&Entry::new(b"cat caf\xe9.txt".to_vec()));
+
+        let bytes = favorites.to_bytes();
+        assert_eq!(
+            bytes,
+            b"cat <<EOF\\nspam\\nEOF\nprintf '\\\\n'\ncat caf\xe9.txt\n"
+        );
+        assert_eq!(Favorites::from_bytes(&bytes).entries, favorites.entries);
+    }
+}
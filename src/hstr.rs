@@ -1,8 +1,64 @@
+use crate::blacklist::Blacklist;
 use crate::io;
-use crate::sort;
+use crate::secrets::Secrets;
+use crate::sort::Timestamp;
 use anyhow::anyhow;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 
-pub type History = Vec<String>;
+pub type History = Vec<Entry>;
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /* Lossily decoded, for display and searching */
+    pub text: String,
+    /* As found in the history file, for output */
+    pub bytes: Vec<u8>,
+    /* Seconds since the epoch, if the history file records it */
+    pub timestamp: Option<i64>,
+    /* Elapsed seconds, if the history file records it */
+    pub duration: Option<u64>,
+}
+
+impl Entry {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            text: String::from_utf8_lossy(&bytes).into_owned(),
+            bytes,
+            timestamp: None,
+            duration: None,
+        }
+    }
+}
+
+/* Entries are the same command regardless of when they were run,
+ * which is what ranking and deduplication rely on. */
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for Entry {}
+
+impl Hash for Entry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state);
+    }
+}
+
+impl Timestamp for Entry {
+    fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+}
+
+impl From<&str> for Entry {
+    fn from(text: &str) -> Self {
+        Self::new(text.as_bytes().to_vec())
+    }
+}
 
 trait FromBytes {
     fn from_bytes(bytes: Vec<u8>) -> History;
@@ -12,28 +68,206 @@ impl FromBytes for History {
     fn from_bytes(bytes: Vec<u8>) -> History {
         bytes
             .split(|byte| *byte == 10) // split on newline
-            .map(|line| String::from_utf8(line.to_vec()).unwrap())
             .filter(|line| !line.is_empty())
+            .map(|line| Entry::new(line.to_vec()))
             .collect()
     }
 }
 
-pub fn get_bash_history() -> History {
-    sort::sort(History::from_bytes(
-        io::read_as_bytes(".bash_history").unwrap(),
-    ))
+pub fn get_history(
+    shell: Shell,
+    histfile: &Path,
+    blacklist: &Blacklist,
+) -> anyhow::Result<History> {
+    let mut history = decode(shell, io::read_as_bytes(histfile)?);
+    history.retain(|entry| !blacklist.contains(entry));
+
+    /* Several shells appending to the same file can leave it out of
+     * chronological order, which ranking by position relies on. */
+    if history.iter().all(|entry| entry.timestamp.is_some()) {
+        history.sort_by_key(|entry| entry.timestamp);
+    }
+
+    Ok(history)
+}
+
+pub fn remove_from_history(shell: Shell, histfile: &Path, entry: &Entry) -> anyhow::Result<()> {
+    io::rewrite(histfile, |history| remove_entry(shell, &history, entry))
+}
+
+pub fn scrub_history(shell: Shell, histfile: &Path, secrets: &Secrets) -> anyhow::Result<()> {
+    io::rewrite(histfile, |history| {
+        remove_entries(shell, &history, |entry| secrets.contains(&entry.text))
+    })
+}
+
+pub fn find_secrets(shell: Shell, histfile: &Path, secrets: &Secrets) -> anyhow::Result<History> {
+    /* The file is searched as it is stored, blacklisted commands
+     * included, so this finds what scrub_history() removes. */
+    let history = io::read_as_bytes(histfile)?;
+    Ok(find_entries(shell, &history, |entry| {
+        secrets.contains(&entry.text)
+    }))
+}
+
+fn decode(shell: Shell, bytes: Vec<u8>) -> History {
+    match shell {
+        Shell::Bash => bash::process_history(bytes),
+        Shell::Zsh => zsh::process_history(bytes),
+        Shell::Fish => fish::process_history(bytes),
+    }
+}
+
+fn remove_entry(shell: Shell, history: &[u8], entry: &Entry) -> Vec<u8> {
+    remove_entries(shell, history, |other| other == entry)
+}
+
+fn remove_entries(shell: Shell, history: &[u8], is_removed: impl Fn(&Entry) -> bool) -> Vec<u8> {
+    /* The history is split into records as they are stored, and
+     * each one is decoded on its own to tell whether it holds the
+     * entry. The remaining records are written back untouched,
+     * so zsh history stays metafied and fish history escaped. */
+    records(shell, history)
+        .into_iter()
+        .filter(|record| !decode_record(shell, record).iter().any(&is_removed))
+        .flatten()
+        .copied()
+        .collect()
+}
+
+fn find_entries(shell: Shell, history: &[u8], is_found: impl Fn(&Entry) -> bool) -> History {
+    records(shell, history)
+        .into_iter()
+        .flat_map(|record| decode_record(shell, record))
+        .filter(is_found)
+        .collect()
+}
+
+fn records(shell: Shell, history: &[u8]) -> Vec<&[u8]> {
+    match shell {
+        Shell::Bash => split_records(history, bash::starts_record(history)),
+        Shell::Zsh => split_records(history, zsh::starts_record),
+        Shell::Fish => split_records(history, fish::starts_record),
+    }
+}
+
+fn decode_record(shell: Shell, record: &[u8]) -> History {
+    /* A bash record can't be told from separate entries without
+     * the rest of the file, so it is decoded as one entry. */
+    match shell {
+        Shell::Bash => bash::process_record(record),
+        Shell::Zsh | Shell::Fish => decode(shell, record.to_vec()),
+    }
+}
+
+fn split_records(
+    history: &[u8],
+    mut starts_record: impl FnMut(&[u8], &[u8]) -> bool,
+) -> Vec<&[u8]> {
+    /* `starts_record` is given each line along with
+     * the one before it, both without the newline. */
+    let mut records = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut previous: &[u8] = &[];
+
+    for line in history.split_inclusive(|byte| *byte == 10) {
+        let stripped = line.strip_suffix(b"\n").unwrap_or(line);
+        if starts_record(stripped, previous) && offset > start {
+            records.push(&history[start..offset]);
+            start = offset;
+        }
+        offset += line.len();
+        previous = stripped;
+    }
+
+    if start < history.len() {
+        records.push(&history[start..]);
+    }
+    records
+}
+
+pub fn history_path(
+    shell: Shell,
+    histfile: Option<PathBuf>,
+    configured: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    /* $HISTFILE takes precedence over --histfile, which in turn
+     * takes precedence over the config file and the shell's default
+     * location. fish doesn't use $HISTFILE, so it isn't consulted
+     * there. */
+    let histfile_var = match shell {
+        Shell::Bash | Shell::Zsh => env::var_os("HISTFILE")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from),
+        Shell::Fish => None,
+    };
+    match histfile_var.or(histfile).or(configured) {
+        Some(path) => io::expand_tilde(path),
+        None => Ok(io::home_dir()?.join(shell.default_histfile())),
+    }
+}
+
+pub fn escape(command: &[u8]) -> Vec<u8> {
+    /* Puts an entry on a single line, the way fish stores it:
+     * newlines become `\n` and backslashes become `\\`. */
+    let mut escaped = Vec::with_capacity(command.len());
+
+    for &byte in command {
+        match byte {
+            b'\n' => escaped.extend(b"\\n"),
+            b'\\' => escaped.extend(b"\\\\"),
+            _ => escaped.push(byte),
+        }
+    }
+    escaped
+}
+
+pub fn from_lines(bytes: &[u8]) -> History {
+    /* One entry per line, escaped like fish history, as the
+     * favorites and blacklist files are written. */
+    bytes
+        .split(|byte| *byte == 10)
+        .filter(|line| !line.is_empty())
+        .map(|line| Entry::new(unescape(line)))
+        .collect()
 }
 
-pub fn get_zsh_history() -> History {
-    sort::sort(zsh::process_history(
-        io::read_as_bytes(".zsh_history").unwrap(),
-    ))
+pub fn to_lines(entries: &[Entry]) -> Vec<u8> {
+    entries
+        .iter()
+        .flat_map(|entry| {
+            let mut line = escape(&entry.bytes);
+            line.push(b'\n');
+            line
+        })
+        .collect()
+}
+
+pub fn unescape(command: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(command.len());
+    let mut bytes = command.iter();
+
+    while let Some(&byte) = bytes.next() {
+        if byte != b'\\' {
+            unescaped.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => unescaped.push(b'\n'),
+            Some(b'\\') => unescaped.push(b'\\'),
+            Some(&other) => unescaped.extend([b'\\', other]),
+            None => unescaped.push(b'\\'),
+        }
+    }
+    unescaped
 }
 
 #[derive(Clone, Copy)]
 pub enum Shell {
     Bash,
     Zsh,
+    Fish,
 }
 
 impl Shell {
@@ -41,17 +275,313 @@ impl Shell {
         match string {
             "bash" => Ok(Shell::Bash),
             "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
             _ => Err(anyhow!("Not implemented for {}", string)),
         }
     }
+
+    fn default_histfile(&self) -> &'static str {
+        /* Relative to the home directory */
+        match self {
+            Shell::Bash => ".bash_history",
+            Shell::Zsh => ".zsh_history",
+            Shell::Fish => ".local/share/fish/fish_history",
+        }
+    }
+
+    pub fn detect() -> anyhow::Result<Self> {
+        /* `setenv` doesn't know about fish and reports it as bash. */
+        match env::var("SHELL") {
+            Ok(shell) if shell.ends_with("/fish") => Ok(Shell::Fish),
+            _ => Shell::from_str(setenv::get_shell().get_name()),
+        }
+    }
+}
+
+mod bash {
+    use super::{Entry, History};
+
+    pub fn process_history(history: Vec<u8>) -> History {
+        /* With HISTTIMEFORMAT set, bash precedes each entry
+         * with a comment holding its timestamp:
+         *
+         * #1330648651
+         * sudo reboot
+         *
+         * With `lithist` set, multi-line entries such as heredocs are
+         * written out as they were typed. Their lines can only be told
+         * apart from separate entries by the timestamps, so everything
+         * up to the next timestamp belongs to the same entry. After the
+         * last timestamp, which may be followed by lines written once
+         * HISTTIMEFORMAT was unset, the entry only goes on for as long
+         * as bash would have asked for more of it.
+         */
+        let lines: Vec<&[u8]> = history.split(|byte| *byte == 10).collect();
+        let last_timestamp = last_timestamp(&lines);
+        let mut timestamp = None;
+        let mut multiline = false;
+        let mut entries = History::new();
+
+        for (idx, line) in lines.into_iter().enumerate() {
+            if let Some(t) = parse_timestamp(line) {
+                timestamp = Some(t);
+                multiline = false;
+                continue;
+            }
+            match entries.last_mut() {
+                Some(entry)
+                    if multiline && (Some(idx) < last_timestamp || is_incomplete(&entry.bytes)) =>
+                {
+                    entry.bytes.push(b'\n');
+                    entry.bytes.extend_from_slice(line);
+                }
+                _ if line.is_empty() => {}
+                _ => {
+                    multiline = timestamp.is_some();
+                    entries.push(Entry {
+                        timestamp: timestamp.take(),
+                        ..Entry::new(line.to_vec())
+                    });
+                }
+            }
+        }
+
+        entries
+            .into_iter()
+            .map(|mut entry| {
+                while entry.bytes.ends_with(b"\n") {
+                    entry.bytes.pop();
+                }
+                Entry {
+                    timestamp: entry.timestamp,
+                    ..Entry::new(entry.bytes)
+                }
+            })
+            .collect()
+    }
+
+    pub fn process_record(record: &[u8]) -> History {
+        /* A record as split by `starts_record`: an optional
+         * timestamp followed by the lines of a single entry. */
+        let (first, rest) = match record.iter().position(|byte| *byte == 10) {
+            Some(i) => (&record[..i], &record[i + 1..]),
+            None => (record, &record[record.len()..]),
+        };
+        let (timestamp, mut bytes) = match parse_timestamp(first) {
+            Some(t) => (Some(t), rest.to_vec()),
+            None => (None, record.to_vec()),
+        };
+        while bytes.ends_with(b"\n") {
+            bytes.pop();
+        }
+        match bytes.is_empty() {
+            true => History::new(),
+            false => vec![Entry {
+                timestamp,
+                ..Entry::new(bytes)
+            }],
+        }
+    }
+
+    pub fn starts_record(history: &[u8]) -> impl FnMut(&[u8], &[u8]) -> bool {
+        /* Lines following a timestamp belong to the same record
+         * until the next timestamp, or after the last timestamp
+         * until the command is complete, as in `process_history`. */
+        let lines: Vec<&[u8]> = history.split(|byte| *byte == 10).collect();
+        let last_timestamp = last_timestamp(&lines);
+        let mut idx = 0;
+        let mut timestamped = false;
+        let mut command: Vec<u8> = Vec::new();
+        move |line, previous| {
+            let before_last = Some(idx) < last_timestamp;
+            idx += 1;
+            if parse_timestamp(line).is_some() {
+                timestamped = true;
+                command.clear();
+                return true;
+            }
+            let continues = timestamped
+                && (parse_timestamp(previous).is_some() || before_last || is_incomplete(&command));
+            if !continues {
+                timestamped = false;
+                command.clear();
+            } else if !command.is_empty() {
+                command.push(b'\n');
+            }
+            command.extend_from_slice(line);
+            !continues
+        }
+    }
+
+    pub fn is_incomplete(command: &[u8]) -> bool {
+        /* Whether bash would still ask for another line of the
+         * command: a heredoc without its delimiter line, an open
+         * quote or compound command, or a line ending in `\`, `|`,
+         * `&&` or `||`. This only tells where an entry ends, and
+         * is no parser. */
+        let command = String::from_utf8_lossy(command);
+        let mut heredocs: Vec<(String, bool)> = Vec::new();
+        let mut quote: Option<char> = None;
+        let mut depth = 0;
+        let mut continued = false;
+
+        for line in command.split('\n') {
+            if quote.is_none() {
+                if let Some((delimiter, tabs)) = heredocs.first() {
+                    let body = match tabs {
+                        true => line.trim_start_matches('\t'),
+                        false => line,
+                    };
+                    if body == delimiter {
+                        heredocs.remove(0);
+                    }
+                    continue;
+                }
+            }
+
+            let chars: Vec<char> = line.chars().collect();
+            let mut word = String::new();
+            let mut command_position = true;
+            let mut i = 0;
+            while i <= chars.len() {
+                let ch = chars.get(i).copied();
+                i += 1;
+                if let Some(q) = quote {
+                    match ch {
+                        Some('\\') if q == '"' => i += 1,
+                        Some(ch) if ch == q => quote = None,
+                        _ => {}
+                    }
+                    word.extend(ch);
+                    continue;
+                }
+                match ch {
+                    Some(ch @ ('\'' | '"')) => {
+                        quote = Some(ch);
+                        word.push(ch);
+                    }
+                    Some('\\') => {
+                        word.push('\\');
+                        word.extend(chars.get(i));
+                        i += 1;
+                    }
+                    Some('#') if word.is_empty() => break,
+                    Some('<') if chars.get(i) == Some(&'<') && chars.get(i + 1) == Some(&'<') => {
+                        /* A here-string has no body. */
+                        i += 2;
+                        word.clear();
+                        command_position = false;
+                    }
+                    Some('<') if chars.get(i) == Some(&'<') => {
+                        /* The delimiter may be quoted, and `<<-`
+                         * strips leading tabs from the body. */
+                        i += 1;
+                        let tabs = chars.get(i) == Some(&'-');
+                        if tabs {
+                            i += 1;
+                        }
+                        while chars.get(i).is_some_and(|ch| *ch == ' ' || *ch == '\t') {
+                            i += 1;
+                        }
+                        let mut delimiter = String::new();
+                        while let Some(&ch) = chars.get(i) {
+                            if ch.is_whitespace() || ";|&<>()".contains(ch) {
+                                break;
+                            }
+                            if !"'\"\\".contains(ch) {
+                                delimiter.push(ch);
+                            }
+                            i += 1;
+                        }
+                        if !delimiter.is_empty() {
+                            heredocs.push((delimiter, tabs));
+                        }
+                        word.clear();
+                        command_position = false;
+                    }
+                    Some(ch) if !ch.is_whitespace() && !";&|()".contains(ch) => word.push(ch),
+                    _ => {
+                        /* A word ends. Reserved words only count
+                         * where a command could start. */
+                        if command_position && !word.is_empty() {
+                            match word.as_str() {
+                                "if" | "case" | "for" | "while" | "until" | "select" | "{" => {
+                                    depth += 1
+                                }
+                                "fi" | "esac" | "done" | "}" => depth -= 1,
+                                _ => {}
+                            }
+                            command_position = matches!(
+                                word.as_str(),
+                                "if" | "then" | "else" | "elif" | "do" | "{" | "}" | "!" | "time"
+                            );
+                        }
+                        if ch.is_some_and(|ch| ";&|()".contains(ch)) {
+                            command_position = true;
+                        }
+                        word.clear();
+                    }
+                }
+            }
+
+            let line = line.trim_end();
+            continued = quote.is_none()
+                && (line.ends_with('\\') || line.ends_with('|') || line.ends_with("&&"));
+        }
+
+        quote.is_some() || !heredocs.is_empty() || depth > 0 || continued
+    }
+
+    fn last_timestamp(lines: &[&[u8]]) -> Option<usize> {
+        lines
+            .iter()
+            .rposition(|line| parse_timestamp(line).is_some())
+    }
+
+    fn parse_timestamp(line: &[u8]) -> Option<i64> {
+        match line.strip_prefix(b"#") {
+            Some(digits) if !digits.is_empty() && digits.iter().all(u8::is_ascii_digit) => {
+                std::str::from_utf8(digits).ok()?.parse().ok()
+            }
+            _ => None,
+        }
+    }
 }
 
 mod zsh {
-    use super::{FromBytes, History};
-    use regex::Regex;
+    use super::{Entry, History};
+    use regex::bytes::Regex;
 
     pub fn process_history(history: Vec<u8>) -> History {
-        remove_timestamps(History::from_bytes(unmetafy(history)))
+        parse_metadata(join_continuations(unmetafy(history)))
+    }
+
+    pub fn starts_record(_line: &[u8], previous: &[u8]) -> bool {
+        !previous.ends_with(b"\\")
+    }
+
+    fn join_continuations(history: Vec<u8>) -> History {
+        /* Newlines within an entry are stored as a backslash
+         * followed by a newline, so such lines are put back
+         * together with the one that follows them. */
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+
+        for line in history.split(|byte| *byte == 10) {
+            match lines.last_mut() {
+                Some(last) if last.ends_with(b"\\") => {
+                    last.pop();
+                    last.push(b'\n');
+                    last.extend_from_slice(line);
+                }
+                _ => lines.push(line.to_vec()),
+            }
+        }
+
+        lines
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .map(Entry::new)
+            .collect()
     }
 
     fn unmetafy(mut bytestring: Vec<u8>) -> Vec<u8> {
@@ -74,16 +604,298 @@ mod zsh {
         bytestring
     }
 
-    fn remove_timestamps(history: History) -> History {
-        /* The preceding metadata needs to be stripped
-         * because zsh history entries look like below:
+    fn parse_metadata(history: History) -> History {
+        /* With EXTENDED_HISTORY set, zsh history entries are
+         * preceded by their start time and elapsed seconds:
          *
          * `: 1330648651:0;sudo reboot`
          */
-        let r = Regex::new(r"^: \d{10}:\d;").unwrap();
+        let r = Regex::new(r"^: (\d+):(\d+);").unwrap();
         history
-            .iter()
-            .map(|line| r.replace(line, "").into_owned())
+            .into_iter()
+            .map(|entry| match r.captures(&entry.bytes) {
+                Some(captures) => Entry {
+                    timestamp: parse_number(&captures[1]),
+                    duration: parse_number(&captures[2]),
+                    ..Entry::new(entry.bytes[captures[0].len()..].to_vec())
+                },
+                None => entry,
+            })
             .collect()
     }
+
+    fn parse_number<T: std::str::FromStr>(digits: &[u8]) -> Option<T> {
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    }
+}
+
+mod fish {
+    use super::{unescape, Entry, FromBytes, History};
+
+    pub fn process_history(history: Vec<u8>) -> History {
+        /* fish history is a YAML-like file where each entry looks like below:
+         *
+         * - cmd: git commit -m "spam\\neggs"
+         *   when: 1330648651
+         *   paths:
+         *     - eggs
+         *
+         * Only the `cmd` and `when` keys are of interest here.
+         */
+        let mut entries = History::new();
+
+        for line in History::from_bytes(history) {
+            if let Some(command) = line.bytes.strip_prefix(b"- cmd: ") {
+                entries.push(Entry::new(unescape(command)));
+            } else if let Some(when) = line.text.strip_prefix("  when: ") {
+                if let Some(entry) = entries.last_mut() {
+                    entry.timestamp = when.parse().ok();
+                }
+            }
+        }
+        entries
+    }
+
+    pub fn starts_record(line: &[u8], _previous: &[u8]) -> bool {
+        line.starts_with(b"- cmd: ")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rstest::rstest;
+
+        #[rstest(
+            command,
+            expected,
+            case("ls -la", "ls -la"),
+            case("echo spam\\neggs", "echo spam\neggs"),
+            case("printf '\\\\n'", "printf '\\n'"),
+            case("echo \\t", "echo \\t")
+        )]
+        fn unescape(command: &str, expected: &str) {
+            assert_eq!(super::unescape(command.as_bytes()), expected.as_bytes());
+        }
+
+        #[test]
+        fn process_history() {
+            let history = b"- cmd: cargo test\n  when: 1330648651\n- cmd: cd ~/src\n  when: 1330648652\n  paths:\n    - ~/src\n";
+            let history = super::process_history(history.to_vec());
+            let commands: Vec<&str> = history.iter().map(|entry| entry.text.as_str()).collect();
+            assert_eq!(commands, vec!["cargo test", "cd ~/src"]);
+            assert_eq!(history[1].timestamp, Some(1330648652));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bash, find_entries, remove_entries, remove_entry, zsh, Entry, Shell};
+    use crate::secrets::Secrets;
+    use rstest::rstest;
+
+    #[test]
+    fn bash_invalid_utf8() {
+        let history = bash::process_history(b"ls -la\ncat caf\xe9.txt\n".to_vec());
+        assert_eq!(history[0], Entry::from("ls -la"));
+        assert_eq!(history[1].text, "cat caf\u{fffd}.txt");
+        assert_eq!(history[1].bytes, b"cat caf\xe9.txt");
+    }
+
+    #[test]
+    fn zsh_invalid_utf8() {
+        /* 0xe9 is metafied into Meta followed by 0xe9 ^ 32 */
+        let history = zsh::process_history(b": 1330648651:0;cat caf\x83\xc9.txt\n".to_vec());
+        assert_eq!(history[0].text, "cat caf\u{fffd}.txt");
+        assert_eq!(history[0].bytes, b"cat caf\xe9.txt");
+    }
+
+    #[test]
+    fn bash_timestamps() {
+        let history =
+            bash::process_history(b"ls -la\n#1330648651\nsudo reboot\n#1330648652\n#x\n".to_vec());
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].timestamp, None);
+        assert_eq!(history[1], Entry::from("sudo reboot"));
+        assert_eq!(history[1].timestamp, Some(1330648651));
+        assert_eq!(history[2], Entry::from("#x"));
+        assert_eq!(history[2].timestamp, Some(1330648652));
+    }
+
+    #[rstest(
+        line,
+        command,
+        timestamp,
+        duration,
+        case(": 1330648651:0;sudo reboot", "sudo reboot", Some(1330648651), Some(0)),
+        case(": 1700000000:125;make", "make", Some(1700000000), Some(125)),
+        case(": 1700000000:3;: noop", ": noop", Some(1700000000), Some(3)),
+        case("sudo reboot", "sudo reboot", None, None)
+    )]
+    fn zsh_metadata(line: &str, command: &str, timestamp: Option<i64>, duration: Option<u64>) {
+        let history = zsh::process_history(line.as_bytes().to_vec());
+        assert_eq!(history[0], Entry::from(command));
+        assert_eq!(history[0].timestamp, timestamp);
+        assert_eq!(history[0].duration, duration);
+    }
+
+    #[test]
+    fn bash_multiline() {
+        let history = b"#1330648651\ncat <<EOF\nspam\n\neggs\nEOF\n#1330648652\nls -la\n";
+        let history = bash::process_history(history.to_vec());
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].bytes, b"cat <<EOF\nspam\n\neggs\nEOF");
+        assert_eq!(history[1], Entry::from("ls -la"));
+    }
+
+    #[test]
+    fn bash_mixed_timestamps() {
+        /* Timestamps stop partway through, as when HISTTIMEFORMAT
+         * is unset, and the lines after the last one are separate
+         * entries. */
+        let history =
+            b"#1700000000\ncat <<EOF\nspam\nEOF\n#1700000001\nls\nmake\ngit status\ncargo test\n";
+        let history = bash::process_history(history.to_vec());
+        assert_eq!(history.len(), 5);
+        assert_eq!(history[0].bytes, b"cat <<EOF\nspam\nEOF");
+        assert_eq!(history[1], Entry::from("ls"));
+        assert_eq!(history[1].timestamp, Some(1700000001));
+        assert_eq!(history[2], Entry::from("make"));
+        assert_eq!(history[2].timestamp, None);
+        assert_eq!(history[4], Entry::from("cargo test"));
+    }
+
+    #[test]
+    fn bash_multiline_last() {
+        /* The newest entry is a heredoc, with no timestamp after it,
+         * and plain lines follow once it is complete. */
+        let history = b"#1700000000\nls\n#1700000001\ncat <<EOF\nspam\n\nEOF\nmake\ngit status\n";
+        let history = bash::process_history(history.to_vec());
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[1].bytes, b"cat <<EOF\nspam\n\nEOF");
+        assert_eq!(history[1].timestamp, Some(1700000001));
+        assert_eq!(history[2], Entry::from("make"));
+        assert_eq!(history[3], Entry::from("git status"));
+    }
+
+    #[rstest(
+        command,
+        expected,
+        case("ls -la", false),
+        case("cat <<EOF", true),
+        case("cat <<EOF\nspam", true),
+        case("cat <<EOF\nspam\nEOF", false),
+        case("cat <<-'EOF' | sort\n\tspam\n\tEOF", false),
+        case("cat <<< spam", false),
+        case("echo 'spam", true),
+        case("echo \"it's\" # don't", false),
+        case("for i in 1 2\ndo", true),
+        case("for i in 1 2\ndo\n  echo $i\ndone", false),
+        case("if true; then ls; fi", false),
+        case("echo done", false),
+        case("case $1 in\n  a) ls;;\nesac", false),
+        case("make &&", true),
+        case("ls |", true),
+        case("ls \\", true)
+    )]
+    fn bash_incomplete(command: &str, expected: bool) {
+        assert_eq!(bash::is_incomplete(command.as_bytes()), expected);
+    }
+
+    #[test]
+    fn zsh_multiline() {
+        let history =
+            b": 1330648651:0;for i in 1 2; do\\\n  echo $i\\\ndone\n: 1330648652:0;ls -la\n";
+        let history = zsh::process_history(history.to_vec());
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].bytes, b"for i in 1 2; do\n  echo $i\ndone");
+        assert_eq!(history[0].timestamp, Some(1330648651));
+        assert_eq!(history[1], Entry::from("ls -la"));
+    }
+
+    #[rstest(
+        shell,
+        history,
+        expected,
+        case(
+            Shell::Bash,
+            "ls -la\nsudo reboot\nls -la\n",
+            "sudo reboot\n"
+        ),
+        case(
+            Shell::Bash,
+            "#1330648651\nls -la\n#1330648652\ncat <<EOF\nls -la\nEOF\n#1330648653\nls -la\n",
+            "#1330648652\ncat <<EOF\nls -la\nEOF\n"
+        ),
+        case(
+            Shell::Bash,
+            "#1330648651\nls -la\n#1330648652\ncat <<EOF\nls -la\nEOF\n",
+            "#1330648652\ncat <<EOF\nls -la\nEOF\n"
+        ),
+        case(
+            Shell::Bash,
+            "#1330648651\nls -la\nmake\nls -la\ngit status\n",
+            "make\ngit status\n"
+        ),
+        case(
+            Shell::Bash,
+            "#1330648651\nmake\nls -la\n",
+            "#1330648651\nmake\n"
+        ),
+        case(
+            Shell::Zsh,
+            ": 1330648651:0;ls -la\n: 1330648652:0;echo \\\nls -la\n: 1330648653:12;ls -la\n",
+            ": 1330648652:0;echo \\\nls -la\n"
+        ),
+        case(
+            Shell::Fish,
+            "- cmd: ls -la\n  when: 1330648651\n- cmd: cd ~/src\n  when: 1330648652\n  paths:\n    - ~/src\n",
+            "- cmd: cd ~/src\n  when: 1330648652\n  paths:\n    - ~/src\n"
+        )
+    )]
+    fn remove(shell: Shell, history: &str, expected: &str) {
+        let history = remove_entry(shell, history.as_bytes(), &Entry::from("ls -la"));
+        assert_eq!(String::from_utf8(history).unwrap(), expected);
+    }
+
+    #[test]
+    fn scrub() {
+        let history = "ls -la\nexport GITHUB_TOKEN=ghp_spam\nmysql -u root -phunter2\nls -la\n";
+        let is_secret = |entry: &Entry| Secrets::default().contains(&entry.text);
+        assert_eq!(
+            find_entries(Shell::Bash, history.as_bytes(), is_secret),
+            vec![
+                Entry::from("export GITHUB_TOKEN=ghp_spam"),
+                Entry::from("mysql -u root -phunter2")
+            ]
+        );
+        let history = remove_entries(Shell::Bash, history.as_bytes(), is_secret);
+        assert_eq!(history, b"ls -la\nls -la\n");
+    }
+
+    #[test]
+    fn scrub_multiline() {
+        /* The secret is in the body of the newest heredoc, which
+         * is removed as a whole. */
+        let history = "#1700000000\nls -la\n#1700000001\nmysql <<EOF\nSET PASSWORD = 'hunter2';\nexport GITHUB_TOKEN=ghp_spam\nEOF\n";
+        let is_secret = |entry: &Entry| Secrets::default().contains(&entry.text);
+        let found = find_entries(Shell::Bash, history.as_bytes(), is_secret);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].bytes,
+            b"mysql <<EOF\nSET PASSWORD = 'hunter2';\nexport GITHUB_TOKEN=ghp_spam\nEOF"
+        );
+        let history = remove_entries(Shell::Bash, history.as_bytes(), is_secret);
+        assert_eq!(history, b"#1700000000\nls -la\n");
+    }
+
+    #[test]
+    fn remove_metafied() {
+        /* "echo Č" with the second byte of Č (0xc4 0x8c) metafied */
+        let history = b": 1330648651:0;echo \xc4\x83\xac\n: 1330648652:0;ls -la\n";
+        let entry = Entry::from("echo Č");
+        assert_eq!(
+            remove_entry(Shell::Zsh, history, &entry),
+            b": 1330648652:0;ls -la\n"
+        );
+    }
 }
@@ -1,34 +1,287 @@
 use crate::hstr::Shell;
+use anyhow::{anyhow, Context};
 use libc::{ioctl, TIOCSTI};
-use std::fs::File;
-use std::io::{Error, Read};
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-pub fn read_as_bytes(path: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
-    /* `path` is relative to the home directory */
-    let home = dirs::home_dir().unwrap();
-    let target = home.join(path);
-    let mut buffer = Vec::new();
+pub fn read_as_bytes(path: impl AsRef<Path>) -> anyhow::Result<Vec<u8>> {
+    let path = path.as_ref();
 
-    if target.exists() {
-        let mut file = File::open(target)?;
-        file.read_to_end(&mut buffer)?;
+    if !path.exists() {
+        return Err(anyhow!("History file {} does not exist", path.display()));
     }
 
-    Ok(buffer)
+    fs::read(path).with_context(|| format!("Could not read history file {}", path.display()))
 }
 
-pub fn echo(command: String) {
-    for byte in command.as_bytes() {
-        unsafe {
-            ioctl(0, TIOCSTI, byte);
+/* How many times the history file is read again when
+ * it changes while it is being rewritten. */
+const REWRITE_ATTEMPTS: usize = 5;
+
+pub fn rewrite(path: &Path, edit: impl Fn(Vec<u8>) -> Vec<u8>) -> anyhow::Result<()> {
+    /* The history file is locked the way zsh does with HIST_FCNTL_LOCK
+     * while it is being rewritten, and the new contents are written
+     * to a temporary file that is renamed over it, so a shell appending
+     * to it never sees it half-written. A symlinked history file is
+     * replaced where the link points, keeping the link. */
+    let path = fs::canonicalize(path)
+        .with_context(|| format!("Could not open history file {}", path.display()))?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Could not open history file {}", path.display()))?;
+    lock(&file).with_context(|| format!("Could not lock history file {}", path.display()))?;
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".hstr-rs");
+    let temporary = PathBuf::from(temporary);
+
+    for _ in 0..REWRITE_ATTEMPTS {
+        /* bash appends without taking the lock, so the file is
+         * only replaced if it didn't change in the meantime, and
+         * is read again otherwise. */
+        let before = fingerprint(&file)?;
+        let contents = edit(fs::read(&path)?);
+
+        let mut new_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temporary)
+            .with_context(|| format!("Could not create {}", temporary.display()))?;
+        new_file.write_all(&contents)?;
+        new_file.sync_all()?;
+        fs::set_permissions(&temporary, file.metadata()?.permissions())?;
+
+        if fingerprint(&file)? == before {
+            fs::rename(&temporary, &path)
+                .with_context(|| format!("Could not replace history file {}", path.display()))?;
+            /* The lock is released when `file` is closed. */
+            return Ok(());
+        }
+    }
+
+    fs::remove_file(&temporary)?;
+    Err(anyhow!(
+        "History file {} kept changing while it was being rewritten",
+        path.display()
+    ))
+}
+
+fn fingerprint(file: &File) -> Result<(u64, Option<SystemTime>), Error> {
+    let metadata = file.metadata()?;
+    Ok((metadata.len(), metadata.modified().ok()))
+}
+
+fn lock(file: &File) -> Result<(), Error> {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLKW, &lock) } == -1 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn home_dir() -> anyhow::Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| anyhow!("Could not determine the home directory"))
+}
+
+pub fn expand_tilde(path: PathBuf) -> anyhow::Result<PathBuf> {
+    /* Only `~` and `~/...` are expanded, `~user` is left alone. */
+    match path.strip_prefix("~") {
+        Ok(rest) => Ok(home_dir()?.join(rest)),
+        Err(_) => Ok(path),
+    }
+}
+
+/* What the shell should do with the selected command. */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Intent {
+    Execute,
+    Edit,
+    Append,
+    Cancel,
+}
+
+impl Intent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Intent::Execute => "execute",
+            Intent::Edit => "edit",
+            Intent::Append => "append",
+            Intent::Cancel => "cancel",
+        }
+    }
+}
+
+pub fn echo(command: Vec<u8>, intent: Intent, output: Option<&Path>) -> anyhow::Result<()> {
+    /* The shell integration passes a file for the selection to be
+     * written to, and reads it back into the line editor. Pushing
+     * the command into the terminal input queue is only a fallback,
+     * since recent kernels disable TIOCSTI by default. */
+    match output {
+        Some(path) => write_to_file(path, &selection(&command, intent)),
+        None => match intent {
+            Intent::Execute => inject(&[command.as_slice(), b"\n"].concat()),
+            Intent::Edit | Intent::Append => inject(&command),
+            Intent::Cancel => Ok(()),
+        },
+    }
+}
+
+fn selection(command: &[u8], intent: Intent) -> Vec<u8> {
+    /* The intent on the first line, and the command, which
+     * may span several lines, as the rest of the file. The
+     * shell keeps its own copy of the line to restore on
+     * cancel, so nothing follows it. */
+    match intent {
+        Intent::Cancel => intent.as_str().as_bytes().to_vec(),
+        _ => [intent.as_str().as_bytes(), b"\n", command].concat(),
+    }
+}
+
+fn write_to_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut file = File::create(path)
+        .with_context(|| format!("Could not open output file {}", path.display()))?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+fn inject(command: &[u8]) -> anyhow::Result<()> {
+    for byte in command {
+        if unsafe { ioctl(0, TIOCSTI, byte) } == -1 {
+            return Err(anyhow!(
+                "TIOCSTI is not permitted ({}). Set up the shell integration from `hstr-rs --show-config <shell>`",
+                Error::last_os_error()
+            ));
         }
     }
+    Ok(())
+}
+
+pub fn print(write: impl FnOnce(&mut dyn Write) -> Result<(), Error>) -> anyhow::Result<()> {
+    let stdout = std::io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    let result = write(&mut writer);
+
+    /* Being piped into something like `head` is not an error. */
+    match result.and_then(|_| writer.flush()) {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
 }
 
 pub fn print_config(shell: Shell) {
     match shell {
         Shell::Bash => println!("{}", include_str!("config/bash")),
         Shell::Zsh => println!("{}", include_str!("config/zsh")),
+        Shell::Fish => println!("{}", include_str!("config/fish")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Intent;
+    use rstest::rstest;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    #[rstest(
+        path,
+        expected,
+        case("~", ""),
+        case("~/.local/state/zsh/history", ".local/state/zsh/history"),
+        case("~spam/.bash_history", "~spam/.bash_history")
+    )]
+    fn expand_tilde(path: &str, expected: &str) {
+        let home = super::home_dir().unwrap();
+        let expanded = super::expand_tilde(PathBuf::from(path)).unwrap();
+        match expanded.strip_prefix(&home) {
+            Ok(rest) => assert_eq!(rest, PathBuf::from(expected)),
+            Err(_) => assert_eq!(expanded, PathBuf::from(expected)),
+        }
+    }
+
+    #[test]
+    fn expand_tilde_absolute() {
+        let path = PathBuf::from("/var/lib/history");
+        assert_eq!(super::expand_tilde(path.clone()).unwrap(), path);
+    }
+
+    #[test]
+    fn rewrite() {
+        let path = std::env::temp_dir().join(format!("hstr-rs-test-{}", std::process::id()));
+        fs::write(&path, "ls -la\nsudo reboot\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        super::rewrite(&path, |contents| contents[7..].to_vec()).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "sudo reboot\n");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rewrite_symlink() {
+        let dir = std::env::temp_dir().join(format!("hstr-rs-symlink-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("bash_history");
+        let link = dir.join("link");
+        fs::write(&target, "ls -la\nsudo reboot\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        super::rewrite(&link, |contents| contents[7..].to_vec()).unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "sudo reboot\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rewrite_concurrent_append() {
+        /* A line appended while the file is being rewritten,
+         * as bash would, is kept. */
+        let path = std::env::temp_dir().join(format!("hstr-rs-append-{}", std::process::id()));
+        fs::write(&path, "ls -la\nsudo reboot\n").unwrap();
+        let appended = std::cell::Cell::new(false);
+
+        super::rewrite(&path, |contents| {
+            if !appended.replace(true) {
+                let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+                std::io::Write::write_all(&mut file, b"make\n").unwrap();
+            }
+            contents[7..].to_vec()
+        })
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "sudo reboot\nmake\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[rstest(
+        intent,
+        expected,
+        case(Intent::Execute, "execute\ngit status"),
+        case(Intent::Edit, "edit\ngit status"),
+        case(Intent::Append, "append\ngit status"),
+        case(Intent::Cancel, "cancel")
+    )]
+    fn selection(intent: Intent, expected: &str) {
+        assert_eq!(super::selection(b"git status", intent), expected.as_bytes());
     }
 }
@@ -0,0 +1,401 @@
+use anyhow::anyhow;
+use ncurses as nc;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    First,
+    Last,
+    CursorLeft,
+    CursorRight,
+    CursorStart,
+    CursorEnd,
+    ForwardWord,
+    BackwardWord,
+    BackwardDeleteChar,
+    DeleteChar,
+    BackwardDeleteWord,
+    BackwardKillWord,
+    KillLine,
+    BackwardKillLine,
+    Yank,
+    ToggleSearchMode,
+    ToggleCase,
+    ToggleSortOrder,
+    ToggleView,
+    ToggleColumns,
+    TogglePreview,
+    ToggleFavorite,
+    DeleteEntry,
+    BlacklistEntry,
+    ToggleMark,
+    Edit,
+    Execute,
+    Append,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Key {
+    /* A character, as returned by get_wch(), a function key
+     * such as nc::KEY_UP, or a character pressed with ALT. */
+    Char(u32),
+    Code(i32),
+    Alt(u32),
+}
+
+impl Key {
+    fn ctrl(ch: char) -> Self {
+        Key::Char(ch as u32 & 0x1f)
+    }
+}
+
+/* How long to wait for the rest of an ALT combination or
+ * escape sequence after ESC, in milliseconds. Short enough
+ * that ESC on its own quits without a noticeable delay. */
+pub const ESC_DELAY: i32 = 25;
+
+pub fn read() -> Option<Key> {
+    /* Terminals send ALT combinations as ESC followed by the
+     * character, so ESC on its own is only known once nothing
+     * follows it. Both 127 and KEY_BACKSPACE are taken as
+     * BACKSPACE after ESC. */
+    let key = match nc::get_wch()? {
+        nc::WchResult::Char(27) => {
+            nc::timeout(ESC_DELAY);
+            let next = nc::get_wch();
+            nc::timeout(-1);
+            match next {
+                Some(nc::WchResult::Char(ch)) => Key::Alt(ch),
+                Some(nc::WchResult::KeyCode(nc::KEY_BACKSPACE)) => Key::Alt(127),
+                Some(nc::WchResult::KeyCode(code)) => Key::Code(code),
+                None => Key::Char(27),
+            }
+        }
+        nc::WchResult::Char(ch) => Key::Char(ch),
+        nc::WchResult::KeyCode(code) => Key::Code(code),
+    };
+    Some(key)
+}
+
+impl TryFrom<String> for Key {
+    type Error = anyhow::Error;
+
+    fn try_from(string: String) -> anyhow::Result<Self> {
+        /* Keys are written as a single character, as the name
+         * of a special key such as `Tab` or `PageUp`, or as `C-`
+         * or `M-` followed by the character to be pressed together
+         * with CTRL or ALT. Names are case-insensitive. */
+        if let Some(rest) = string.strip_prefix("M-") {
+            let mut chars = rest.chars();
+            return match (chars.next(), chars.next()) {
+                _ if rest.eq_ignore_ascii_case("backspace") => Ok(Key::Alt(127)),
+                _ if rest.eq_ignore_ascii_case("enter") => Ok(Key::Alt(10)),
+                (Some(ch), None) => Ok(Key::Alt(ch as u32)),
+                _ => Err(anyhow!("Invalid key {:?}", string)),
+            };
+        }
+        let key = match string.to_ascii_lowercase().as_str() {
+            "tab" => Key::Char(9),
+            "enter" => Key::Char(10),
+            "esc" => Key::Char(27),
+            "c-space" => Key::Char(0),
+            "backspace" => Key::Code(nc::KEY_BACKSPACE),
+            "delete" => Key::Code(nc::KEY_DC),
+            "up" => Key::Code(nc::KEY_UP),
+            "down" => Key::Code(nc::KEY_DOWN),
+            "left" => Key::Code(nc::KEY_LEFT),
+            "right" => Key::Code(nc::KEY_RIGHT),
+            "home" => Key::Code(nc::KEY_HOME),
+            "end" => Key::Code(nc::KEY_END),
+            "pageup" => Key::Code(nc::KEY_PPAGE),
+            "pagedown" => Key::Code(nc::KEY_NPAGE),
+            "s-up" => Key::Code(nc::KEY_SR),
+            "s-down" => Key::Code(nc::KEY_SF),
+            _ => {
+                let (ctrl, rest) = match string.strip_prefix("C-") {
+                    Some(rest) => (true, rest),
+                    None => (false, string.as_str()),
+                };
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) if ctrl && ('@'..='_').contains(&ch.to_ascii_uppercase()) => {
+                        Key::ctrl(ch.to_ascii_uppercase())
+                    }
+                    (Some('/'), None) if ctrl => Key::Char(31),
+                    (Some(ch), None) if !ctrl => Key::Char(ch as u32),
+                    _ => return Err(anyhow!("Invalid key {:?}", string)),
+                }
+            }
+        };
+        Ok(key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /* How keys are shown in the label and the status bar. */
+        match *self {
+            Key::Char(0) => write!(f, "C-SPACE"),
+            Key::Char(9) => write!(f, "TAB"),
+            Key::Char(10) => write!(f, "ENTER"),
+            Key::Char(27) => write!(f, "ESC"),
+            Key::Char(31) => write!(f, "C-/"),
+            Key::Char(127) => write!(f, "BACKSPACE"),
+            Key::Char(ch @ 0..=31) => {
+                let ch = std::char::from_u32(ch | 0x40).unwrap_or('?');
+                write!(f, "C-{}", ch.to_ascii_lowercase())
+            }
+            Key::Char(ch) => write!(f, "{}", std::char::from_u32(ch).unwrap_or('?')),
+            Key::Alt(10) => write!(f, "M-ENTER"),
+            Key::Alt(127) => write!(f, "M-BACKSPACE"),
+            Key::Alt(ch) => write!(f, "M-{}", std::char::from_u32(ch).unwrap_or('?')),
+            Key::Code(code) => match code {
+                nc::KEY_BACKSPACE => write!(f, "BACKSPACE"),
+                nc::KEY_DC => write!(f, "DEL"),
+                nc::KEY_UP => write!(f, "UP"),
+                nc::KEY_DOWN => write!(f, "DOWN"),
+                nc::KEY_LEFT => write!(f, "LEFT"),
+                nc::KEY_RIGHT => write!(f, "RIGHT"),
+                nc::KEY_HOME => write!(f, "HOME"),
+                nc::KEY_END => write!(f, "END"),
+                nc::KEY_PPAGE => write!(f, "PGUP"),
+                nc::KEY_NPAGE => write!(f, "PGDN"),
+                nc::KEY_SR => write!(f, "S-UP"),
+                nc::KEY_SF => write!(f, "S-DOWN"),
+                _ => write!(f, "<{}>", code),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /* The name used in the config file, such as `toggle_case`. */
+        let mut name = String::new();
+        for ch in format!("{:?}", self).chars() {
+            if ch.is_ascii_uppercase() && !name.is_empty() {
+                name.push('_');
+            }
+            name.push(ch.to_ascii_lowercase());
+        }
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum KeyList {
+    One(Key),
+    Many(Vec<Key>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "HashMap<Action, KeyList>")]
+pub struct Bindings {
+    bindings: Vec<(Action, Vec<Key>)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| Key::try_from(name.to_string()).unwrap())
+                .collect()
+        };
+        Self {
+            bindings: vec![
+                (Action::MoveUp, names(&["Up", "C-p"])),
+                (Action::MoveDown, names(&["Down", "C-n"])),
+                (Action::PageUp, names(&["PageUp"])),
+                (Action::PageDown, names(&["PageDown"])),
+                (Action::HalfPageUp, names(&["M-u", "S-Up"])),
+                (Action::HalfPageDown, names(&["M-d", "S-Down"])),
+                (Action::First, names(&["Home"])),
+                (Action::Last, names(&["End"])),
+                (Action::CursorLeft, names(&["Left"])),
+                (Action::CursorRight, names(&["Right"])),
+                (Action::CursorStart, names(&["C-a"])),
+                (Action::CursorEnd, names(&["C-e"])),
+                (Action::ForwardWord, names(&["M-f"])),
+                (Action::BackwardWord, names(&["M-b"])),
+                (
+                    Action::BackwardDeleteChar,
+                    vec![Key::Code(nc::KEY_BACKSPACE), Key::Char(127), Key::Char(8)],
+                ),
+                (Action::DeleteChar, names(&["C-d"])),
+                (Action::BackwardDeleteWord, names(&["C-w"])),
+                (Action::BackwardKillWord, names(&["M-Backspace"])),
+                (Action::KillLine, names(&["C-k"])),
+                (Action::BackwardKillLine, names(&["C-u"])),
+                (Action::Yank, names(&["C-y"])),
+                (Action::ToggleSearchMode, names(&["C-r"])),
+                (Action::ToggleCase, names(&["C-t"])),
+                (Action::ToggleSortOrder, names(&["C-/"])),
+                (Action::ToggleView, names(&["C-g"])),
+                (Action::ToggleColumns, names(&["C-o"])),
+                (Action::TogglePreview, names(&["C-b"])),
+                (Action::ToggleFavorite, names(&["C-f"])),
+                (Action::DeleteEntry, names(&["Delete"])),
+                (Action::BlacklistEntry, names(&["C-l"])),
+                (Action::ToggleMark, names(&["C-Space"])),
+                (Action::Edit, names(&["Tab"])),
+                (Action::Execute, names(&["Enter"])),
+                (Action::Append, names(&["M-Enter"])),
+                (Action::Quit, names(&["Esc"])),
+            ],
+        }
+    }
+}
+
+impl TryFrom<HashMap<Action, KeyList>> for Bindings {
+    type Error = anyhow::Error;
+
+    fn try_from(overrides: HashMap<Action, KeyList>) -> anyhow::Result<Self> {
+        /* Each action given in the config file replaces the default
+         * keys of that action. Keys it takes over are unbound from
+         * the action they had by default. A key can only be given
+         * to one action. */
+        let mut overrides: Vec<(Action, Vec<Key>)> = overrides
+            .into_iter()
+            .map(|(action, keys)| match keys {
+                KeyList::One(key) => (action, vec![key]),
+                KeyList::Many(keys) => (action, keys),
+            })
+            .collect();
+        overrides.sort_by_key(|(action, _)| *action);
+
+        for (i, (action, keys)) in overrides.iter().enumerate() {
+            for (other, other_keys) in &overrides[i + 1..] {
+                if let Some(key) = keys.iter().find(|key| other_keys.contains(key)) {
+                    return Err(anyhow!(
+                        "Key {} is bound to both {} and {}",
+                        key,
+                        action,
+                        other
+                    ));
+                }
+            }
+        }
+
+        let mut bindings = Self::default();
+        for (action, keys) in overrides {
+            for (other, other_keys) in bindings.bindings.iter_mut() {
+                if *other == action {
+                    *other_keys = keys.clone();
+                } else {
+                    other_keys.retain(|key| !keys.contains(key));
+                }
+            }
+        }
+        Ok(bindings)
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn key(&self, action: Action) -> Option<Key> {
+        /* The first key bound to the action, which is the one
+         * shown in the label and the status bar. */
+        self.bindings
+            .iter()
+            .find(|(other, _)| *other == action)
+            .and_then(|(_, keys)| keys.first().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        string,
+        expected,
+        case("C-e", Key::Char(5)),
+        case("C-T", Key::Char(20)),
+        case("C-/", Key::Char(31)),
+        case("C-Space", Key::Char(0)),
+        case("Tab", Key::Char(9)),
+        case("Esc", Key::Char(27)),
+        case("pageup", Key::Code(nc::KEY_PPAGE)),
+        case("q", Key::Char(113)),
+        case("Q", Key::Char(81)),
+        case("M-f", Key::Alt(102)),
+        case("M-backspace", Key::Alt(127)),
+        case("M-Enter", Key::Alt(10))
+    )]
+    fn key(string: &str, expected: Key) {
+        assert_eq!(Key::try_from(string.to_string()).unwrap(), expected);
+    }
+
+    #[rstest(
+        key,
+        expected,
+        case(Key::Char(5), "C-e"),
+        case(Key::Char(31), "C-/"),
+        case(Key::Char(0), "C-SPACE"),
+        case(Key::Char(10), "ENTER"),
+        case(Key::Char(113), "q"),
+        case(Key::Alt(98), "M-b"),
+        case(Key::Alt(10), "M-ENTER"),
+        case(Key::Code(nc::KEY_DC), "DEL")
+    )]
+    fn display(key: Key, expected: &str) {
+        assert_eq!(key.to_string(), expected);
+    }
+
+    #[test]
+    fn overrides() {
+        let overrides = vec![
+            (Action::ToggleCase, KeyList::One(Key::Char(24))),
+            (
+                Action::ToggleView,
+                KeyList::Many(vec![Key::Char(20), Key::Char(7)]),
+            ),
+        ];
+        let bindings =
+            Bindings::try_from(overrides.into_iter().collect::<HashMap<_, _>>()).unwrap();
+        assert_eq!(bindings.action(Key::Char(24)), Some(Action::ToggleCase));
+        assert_eq!(bindings.action(Key::Char(20)), Some(Action::ToggleView));
+        assert_eq!(bindings.key(Action::ToggleView), Some(Key::Char(20)));
+        assert_eq!(bindings.action(Key::Char(16)), Some(Action::MoveUp));
+        assert_eq!(bindings.action(Key::Char(113)), None);
+    }
+
+    #[test]
+    fn duplicate_overrides() {
+        let overrides = vec![
+            (Action::ToggleCase, KeyList::One(Key::Char(24))),
+            (
+                Action::ToggleView,
+                KeyList::Many(vec![Key::Char(7), Key::Char(24)]),
+            ),
+        ];
+        let error = Bindings::try_from(overrides.into_iter().collect::<HashMap<_, _>>())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "Key C-x is bound to both toggle_case and toggle_view"
+        );
+    }
+}
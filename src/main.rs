@@ -1,26 +1,40 @@
-use crate::ui::Direction;
-use hstr::Shell;
+use crate::export::{Format, Redaction};
+use crate::favorites::Favorites;
+use crate::io::Intent;
+use crate::keys::{Action, Key};
+use crate::settings::Settings;
+use crate::sort::SortOrder;
+use crate::state::{Query, SearchMode, State};
+use crate::stats::Stats;
+use crate::ui::pp::single_line;
+use crate::ui::{Direction, Join};
+use hstr::{History, Shell};
+use itertools::Itertools;
 use ncurses as nc;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
+mod blacklist;
+mod export;
+mod favorites;
 mod hstr;
 mod io;
+mod keys;
+mod secrets;
+mod settings;
 mod sort;
 mod state;
+mod stats;
+mod theme;
 mod ui;
 
-const CTRL_E: u32 = 5;
-const TAB: u32 = 9;
-const ENTER: u32 = 10;
-const CTRL_T: u32 = 20;
-const ESC: u32 = 27;
-
 fn main() {
     let args = Opt::from_args();
     let result = run(args);
 
     if let Err(e) = result {
         eprintln!("hstr-rs error: {:?}", e);
+        std::process::exit(1);
     }
 }
 
@@ -32,108 +46,294 @@ fn run(args: Opt) -> anyhow::Result<()> {
         return Ok(());
     }
 
+    let settings = Settings::load(args.config)?;
+
     let query = args.query.unwrap_or_default();
-    let mut user_interface = ui::UserInterface::new(&query).unwrap();
+    let sort_order = match args.sort {
+        Some(sort_order) => SortOrder::from_str(&sort_order)?,
+        None => settings.sort_order,
+    };
+    let shell = Shell::detect()?;
+    let histfile = hstr::history_path(shell, args.histfile, settings.histfile.for_shell(shell))?;
+    let blacklist_path = blacklist::default_path()?;
+    let mut blacklist = settings.blacklist;
+    blacklist.load(&blacklist_path)?;
+    let history = hstr::get_history(shell, &histfile, &blacklist)?;
+
+    match args.command {
+        /* If the stats subcommand was given, print statistics and exit. */
+        Some(Command::Stats { top, json }) => {
+            let stats = Stats::new(&history, top, &settings.secrets);
+            return io::print(|writer| match json {
+                true => stats.write_json(writer),
+                false => stats.write_text(writer),
+            });
+        }
+        /* If the scrub subcommand was given, list the commands with
+         * secrets in the history file, remove them if asked to, and
+         * exit. */
+        Some(Command::Scrub { yes }) => {
+            let secrets = &settings.secrets;
+            let found: History = hstr::find_secrets(shell, &histfile, secrets)?
+                .into_iter()
+                .unique()
+                .collect();
+            if yes {
+                hstr::scrub_history(shell, &histfile, secrets)?;
+            }
+            return io::print(|writer| {
+                found.iter().try_for_each(|entry| {
+                    writeln!(writer, "{}", single_line(&secrets.mask(&entry.text)))
+                })
+            });
+        }
+        None => (),
+    }
+
+    let favorites_path = favorites::default_path()?;
+
+    let mut state = State::new(&query, history, sort_order);
+    state.favorites = Favorites::load(&favorites_path)?;
+    state.histfile = histfile.clone();
+    state.secrets = settings.secrets;
+    state.search_mode = match args.mode {
+        Some(search_mode) => SearchMode::from_str(&search_mode)?,
+        None => settings.search_mode,
+    };
+    state.case_sensitivity = settings.case_sensitivity;
+
+    /* If --list or --filter was passed, print the matching
+     * entries and exit without bringing up the interface. */
+    if args.list || args.filter.is_some() {
+        state.query = Query::new(&args.filter.unwrap_or_default());
+        state.try_search()?;
+        let format = Format::from_str(&args.format)?;
+        let redaction = Redaction::from_str(&args.secrets)?;
+        let separator = if args.null { b'\0' } else { b'\n' };
+        return io::print(|writer| {
+            export::write(
+                writer,
+                &state.search_results,
+                &state.metadata,
+                &state.secrets,
+                redaction,
+                format,
+                separator,
+            )
+        });
+    }
 
-    ui::curses::init();
+    let join = match args.join {
+        Some(join) => Join::from_str(&join)?,
+        None => settings.join,
+    };
+
+    let mut user_interface = ui::UserInterface::new(state);
+    user_interface.scrolling = settings.scrolling;
+    user_interface.keys = settings.keys;
+    user_interface.columns = settings.columns;
+    user_interface.preview = settings.preview;
+
+    ui::curses::init(theme::palette(settings.theme, &settings.colors));
 
     /* If a search query was passed when hstr was started, search
-     * and move the cursor to the end of the query. */
+     * for it. The cursor starts at the end of the query. */
     if !query.is_empty() {
         user_interface.state.search();
-
-        for _ in 0..query.chars().count() {
-            user_interface.move_cursor(Direction::Forward);
-        }
     }
 
     user_interface.populate_screen();
 
+    let mut selection = None;
+
     loop {
-        let user_input = nc::get_wch();
+        let key = match keys::read() {
+            Some(key) => key,
+            None => continue,
+        };
 
-        match user_input.unwrap() {
-            nc::WchResult::Char(ch) => match ch {
-                CTRL_E => {
-                    user_interface.state.toggle_search_mode();
-                    user_interface.set_highlighted(0);
-                    user_interface.populate_screen();
-                }
-                TAB => match user_interface.compute_highlighted() {
-                    Some(command) => {
-                        io::echo(command);
-                        break;
-                    }
-                    None => continue,
-                },
-                ENTER => match user_interface.compute_highlighted() {
-                    Some(command) => {
-                        io::echo(command + "\n");
-                        break;
+        let action = match user_interface.keys.action(key) {
+            Some(action) => action,
+            None => {
+                /* Keys that aren't bound to an action are typed
+                 * into the query, unless they are control keys. */
+                match key {
+                    Key::Char(ch) => match std::char::from_u32(ch) {
+                        Some(ch) if !ch.is_control() => {
+                            user_interface.edit_query(|query| query.insert_char(ch))
+                        }
+                        _ => {}
+                    },
+                    Key::Code(nc::KEY_RESIZE) => {
+                        user_interface.fit_highlighted();
+                        nc::clear();
+                        user_interface.populate_screen();
                     }
-                    None => continue,
-                },
-                CTRL_T => {
-                    user_interface.state.toggle_case();
-                    user_interface.populate_screen();
+                    Key::Code(_) | Key::Alt(_) => {}
                 }
-                ESC => break,
-                _ => {
-                    let ch = std::char::from_u32(ch).unwrap();
-                    user_interface
-                        .state
-                        .query
-                        .insert_char(user_interface.get_cursor_position(), ch);
-                    user_interface.set_highlighted(0);
-                    user_interface.set_page(1);
-                    nc::clear();
+                continue;
+            }
+        };
+
+        match action {
+            Action::MoveUp => {
+                user_interface.move_highlighted(Direction::Backward);
+                user_interface.populate_screen();
+            }
+            Action::MoveDown => {
+                user_interface.move_highlighted(Direction::Forward);
+                user_interface.populate_screen();
+            }
+            Action::PageUp => {
+                user_interface.turn_page(Direction::Backward);
+                user_interface.populate_screen();
+            }
+            Action::PageDown => {
+                user_interface.turn_page(Direction::Forward);
+                user_interface.populate_screen();
+            }
+            Action::HalfPageUp => {
+                user_interface.move_half_page(Direction::Backward);
+                user_interface.populate_screen();
+            }
+            Action::HalfPageDown => {
+                user_interface.move_half_page(Direction::Forward);
+                user_interface.populate_screen();
+            }
+            Action::First => {
+                user_interface.move_to_first();
+                user_interface.populate_screen();
+            }
+            Action::Last => {
+                user_interface.move_to_last();
+                user_interface.populate_screen();
+            }
+            Action::CursorLeft => user_interface.edit_query(Query::move_left),
+            Action::CursorRight => user_interface.edit_query(Query::move_right),
+            Action::CursorStart => user_interface.edit_query(Query::move_start),
+            Action::CursorEnd => user_interface.edit_query(Query::move_end),
+            Action::ForwardWord => user_interface.edit_query(Query::forward_word),
+            Action::BackwardWord => user_interface.edit_query(Query::backward_word),
+            Action::BackwardDeleteChar => user_interface.edit_query(Query::backward_delete_char),
+            Action::DeleteChar => user_interface.edit_query(Query::delete_char),
+            Action::BackwardDeleteWord => user_interface.edit_query(Query::backward_delete_word),
+            Action::BackwardKillWord => user_interface.edit_query(Query::backward_kill_word),
+            Action::KillLine => user_interface.edit_query(Query::kill_line),
+            Action::BackwardKillLine => user_interface.edit_query(Query::backward_kill_line),
+            Action::Yank => user_interface.edit_query(Query::yank),
+            Action::ToggleSearchMode => {
+                user_interface.state.toggle_search_mode();
+                user_interface.reset_highlighted();
+                user_interface.populate_screen();
+            }
+            Action::ToggleCase => {
+                user_interface.state.toggle_case();
+                user_interface.populate_screen();
+            }
+            Action::ToggleSortOrder => {
+                user_interface.state.toggle_sort_order();
+                user_interface.state.search();
+                user_interface.reset_highlighted();
+                nc::clear();
+                user_interface.populate_screen();
+            }
+            Action::ToggleView => {
+                user_interface.state.toggle_view();
+                user_interface.state.search();
+                user_interface.reset_highlighted();
+                nc::clear();
+                user_interface.populate_screen();
+            }
+            Action::ToggleColumns => {
+                user_interface.columns = !user_interface.columns;
+                user_interface.populate_screen();
+            }
+            Action::TogglePreview => {
+                user_interface.preview = !user_interface.preview;
+                user_interface.fit_highlighted();
+                nc::clear();
+                user_interface.populate_screen();
+            }
+            Action::ToggleFavorite => {
+                if let Some(entry) = user_interface.compute_highlighted() {
+                    user_interface.state.favorites.toggle(&entry);
+                    let result = user_interface.state.favorites.save(&favorites_path);
                     user_interface.state.search();
-                    user_interface.populate_screen();
-                    user_interface.move_cursor(Direction::Forward);
-                }
-            },
-            nc::WchResult::KeyCode(code) => match code {
-                nc::KEY_LEFT => user_interface.move_cursor(Direction::Backward),
-                nc::KEY_RIGHT => user_interface.move_cursor(Direction::Forward),
-                nc::KEY_UP => {
-                    user_interface.move_highlighted(Direction::Backward);
-                    user_interface.populate_screen();
-                }
-                nc::KEY_DOWN => {
-                    user_interface.move_highlighted(Direction::Forward);
-                    user_interface.populate_screen();
-                }
-                nc::KEY_BACKSPACE => {
-                    if !user_interface.state.query.text.is_empty() {
-                        user_interface
-                            .state
-                            .query
-                            .remove_char(user_interface.get_cursor_position());
-                    }
+                    user_interface.fit_highlighted();
                     nc::clear();
-                    user_interface.state.search();
-                    user_interface.populate_screen();
-                    user_interface.move_cursor(Direction::Backward);
-                }
-                nc::KEY_NPAGE => {
-                    user_interface.turn_page(Direction::Forward);
                     user_interface.populate_screen();
+                    if let Err(e) = result {
+                        user_interface.show_error(&format!("hstr-rs error: {:#}", e));
+                    }
                 }
-                nc::KEY_PPAGE => {
-                    user_interface.turn_page(Direction::Backward);
+            }
+            Action::DeleteEntry => {
+                if let Some(entry) = user_interface.compute_highlighted() {
+                    let question = format!(
+                        "Delete `{}` from history?",
+                        user_interface.state.secrets.mask(&entry.text)
+                    );
+                    let result = if user_interface.confirm(&question) {
+                        hstr::remove_from_history(shell, &histfile, &entry)
+                            .map(|_| user_interface.remove(&entry))
+                    } else {
+                        Ok(())
+                    };
+                    nc::clear();
                     user_interface.populate_screen();
+                    if let Err(e) = result {
+                        user_interface.show_error(&format!("hstr-rs error: {:#}", e));
+                    }
                 }
-                nc::KEY_RESIZE => {
+            }
+            Action::BlacklistEntry => {
+                if let Some(entry) = user_interface.compute_highlighted() {
+                    let question = format!(
+                        "Blacklist `{}`?",
+                        user_interface.state.secrets.mask(&entry.text)
+                    );
+                    let result = if user_interface.confirm(&question) {
+                        blacklist.add(&entry);
+                        blacklist
+                            .save(&blacklist_path)
+                            .map(|_| user_interface.remove(&entry))
+                    } else {
+                        Ok(())
+                    };
                     nc::clear();
                     user_interface.populate_screen();
+                    if let Err(e) = result {
+                        user_interface.show_error(&format!("hstr-rs error: {:#}", e));
+                    }
                 }
-                _ => {}
-            },
+            }
+            Action::ToggleMark => {
+                user_interface.toggle_mark();
+                user_interface.move_highlighted(Direction::Forward);
+                user_interface.populate_screen();
+            }
+            Action::Edit | Action::Execute | Action::Append => {
+                if let Some(command) = user_interface.compute_selection(join) {
+                    let intent = match action {
+                        Action::Execute => Intent::Execute,
+                        Action::Append => Intent::Append,
+                        _ => Intent::Edit,
+                    };
+                    selection = Some((command, intent));
+                    break;
+                }
+            }
+            Action::Quit => break,
         }
     }
 
     ui::curses::teardown();
 
+    /* Leaving without choosing a command tells the shell
+     * to put back the line hstr was started from. */
+    let (command, intent) = selection.unwrap_or((Vec::new(), Intent::Cancel));
+    io::echo(command, intent, args.output.as_deref())?;
+
     Ok(())
 }
 
@@ -142,4 +342,47 @@ struct Opt {
     query: Option<String>,
     #[structopt(name = "show-config", long)]
     show_config: Option<String>,
+    #[structopt(name = "output", long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    #[structopt(name = "histfile", long, parse(from_os_str))]
+    histfile: Option<PathBuf>,
+    #[structopt(name = "sort", long)]
+    sort: Option<String>,
+    #[structopt(name = "config", long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    #[structopt(name = "mode", long)]
+    mode: Option<String>,
+    #[structopt(name = "list", long)]
+    list: bool,
+    #[structopt(name = "filter", long)]
+    filter: Option<String>,
+    #[structopt(name = "null", short = "0", long)]
+    null: bool,
+    #[structopt(name = "format", long, default_value = "plain")]
+    format: String,
+    #[structopt(name = "join", long)]
+    join: Option<String>,
+    #[structopt(name = "secrets", long, default_value = "mask")]
+    secrets: String,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Print statistics about the history
+    Stats {
+        /// Number of commands and executables to list
+        #[structopt(name = "top", long, default_value = "10")]
+        top: usize,
+        /// Print the statistics as JSON
+        #[structopt(name = "json", long)]
+        json: bool,
+    },
+    /// List the commands with secrets in them, and remove them from the history file with --yes
+    Scrub {
+        /// Remove the listed commands from the history file
+        #[structopt(name = "yes", long)]
+        yes: bool,
+    },
 }
@@ -0,0 +1,208 @@
+use crate::blacklist::Blacklist;
+use crate::hstr::Shell;
+use crate::io;
+use crate::keys::Bindings;
+use crate::secrets::Secrets;
+use crate::sort::SortOrder;
+use crate::state::SearchMode;
+use crate::theme::{Colors, Theme};
+use crate::ui::{Join, Scrolling};
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub search_mode: SearchMode,
+    pub case_sensitivity: bool,
+    pub sort_order: SortOrder,
+    pub scrolling: Scrolling,
+    pub columns: bool,
+    pub preview: bool,
+    pub join: Join,
+    pub theme: Theme,
+    pub colors: Colors,
+    pub keys: Bindings,
+    pub blacklist: Blacklist,
+    pub secrets: Secrets,
+    pub histfile: HistFiles,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            search_mode: SearchMode::Exact,
+            case_sensitivity: false,
+            sort_order: SortOrder::Frequency,
+            scrolling: Scrolling::Smooth,
+            columns: false,
+            preview: false,
+            join: Join::And,
+            theme: Theme::Default,
+            colors: Colors::default(),
+            keys: Bindings::default(),
+            blacklist: Blacklist::default(),
+            secrets: Secrets::default(),
+            histfile: HistFiles::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: Option<PathBuf>) -> anyhow::Result<Self> {
+        /* A config file passed with --config has to exist, while
+         * the one in the XDG config directory is optional. */
+        let path = match path {
+            Some(path) => io::expand_tilde(path)?,
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        Self::from_toml(&contents)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    fn from_toml(contents: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    /* $XDG_CONFIG_HOME/hstr-rs/config.toml, or ~/.config/hstr-rs/config.toml */
+    dirs::config_dir().map(|dir| dir.join("hstr-rs").join("config.toml"))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistFiles {
+    pub bash: Option<PathBuf>,
+    pub zsh: Option<PathBuf>,
+    pub fish: Option<PathBuf>,
+}
+
+impl HistFiles {
+    pub fn for_shell(&self, shell: Shell) -> Option<PathBuf> {
+        match shell {
+            Shell::Bash => self.bash.clone(),
+            Shell::Zsh => self.zsh.clone(),
+            Shell::Fish => self.fish.clone(),
+        }
+    }
+}
+
+/* A regex written as a string in the config file, such as
+ * the patterns of the blacklist and of secrets. */
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = anyhow::Error;
+
+    fn try_from(string: String) -> anyhow::Result<Self> {
+        Ok(Self(Regex::new(&string)?))
+    }
+}
+
+impl Pattern {
+    pub fn regex(&self) -> &Regex {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hstr::Entry;
+    use crate::keys::{Action, Key};
+    use crate::theme::{Color, ColorPair};
+    use rstest::rstest;
+
+    #[test]
+    fn from_toml() {
+        let settings = Settings::from_toml(
+            r##"
+            search_mode = "fuzzy"
+            sort_order = "frecency"
+            scrolling = "page"
+            join = "newline"
+            theme = "high-contrast"
+
+            [colors]
+            selected = { fg = "#fdf6e3", bg = 33 }
+
+            [keys]
+            toggle_case = "C-x"
+
+            [blacklist]
+            commands = ["ls", "pwd"]
+            patterns = ["^cd "]
+
+            [secrets]
+            patterns = ["vault login (?P<secret>\\S+)"]
+
+            [histfile]
+            zsh = "~/.local/state/zsh/history"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(settings.search_mode, SearchMode::Fuzzy);
+        assert!(!settings.case_sensitivity);
+        assert_eq!(settings.sort_order, SortOrder::Frecency);
+        assert_eq!(settings.scrolling, Scrolling::Page);
+        assert_eq!(settings.join, Join::Newline);
+        assert_eq!(settings.theme, Theme::HighContrast);
+        assert_eq!(
+            settings.colors.selected,
+            Some(ColorPair::new(
+                Color::Rgb(0xfd, 0xf6, 0xe3),
+                Color::Indexed(33)
+            ))
+        );
+        assert_eq!(settings.colors.status, None);
+        assert_eq!(
+            settings.keys.action(Key::Char(24)),
+            Some(Action::ToggleCase)
+        );
+        assert_eq!(settings.keys.action(Key::Char(20)), None);
+        assert_eq!(settings.keys.action(Key::Char(27)), Some(Action::Quit));
+        assert!(settings.blacklist.contains(&Entry::from("pwd")));
+        assert!(settings.blacklist.contains(&Entry::from("cd /tmp")));
+        assert!(!settings.blacklist.contains(&Entry::from("ls -la")));
+        assert_eq!(
+            settings.secrets.mask("vault login s.spam"),
+            "vault login ******"
+        );
+        assert_eq!(
+            settings.histfile.for_shell(Shell::Zsh),
+            Some(PathBuf::from("~/.local/state/zsh/history"))
+        );
+    }
+
+    #[rstest(
+        contents,
+        case("serch_mode = \"regex\""),
+        case("[colors]\nhighlight = { fg = \"red\", bg = \"black\" }"),
+        case("[keys]\nquit = \"C-ä\""),
+        case("[keys]\nexit = \"Esc\""),
+        case("[colors]\nstatus = { fg = \"orange\", bg = \"black\" }"),
+        case("[colors]\nstatus = { fg = 256, bg = \"black\" }"),
+        case("theme = \"solarized\""),
+        case("[blacklist]\npatterns = [\"(\"]")
+    )]
+    fn invalid(contents: &str) {
+        assert!(Settings::from_toml(contents).is_err());
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(Pattern::try_from("(".to_string()).is_err());
+    }
+}
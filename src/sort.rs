@@ -1,20 +1,94 @@
+use anyhow::anyhow;
+use serde::Deserialize;
 use std::cmp::{Eq, Reverse};
 use std::collections::HashMap;
 use std::hash::Hash;
 
-pub fn sort<T>(mut history: Vec<T>) -> Vec<T>
+/* The weight of an occurrence halves with every week
+ * that has passed since, or with every 500 commands run
+ * after it when the history has no timestamps. */
+const HALF_LIFE_SECONDS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+const HALF_LIFE_ENTRIES: f64 = 500.0;
+
+pub trait Timestamp {
+    fn timestamp(&self) -> Option<i64>;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Metadata {
+    pub count: usize,
+    pub last_position: usize,
+    pub first_seen: Option<i64>,
+    pub last_seen: Option<i64>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Frequency = 0,
+    Frecency = 1,
+    Recency = 2,
+    Raw = 3,
+}
+
+impl SortOrder {
+    pub fn from_str(string: &str) -> anyhow::Result<Self> {
+        match string {
+            "frequency" => Ok(SortOrder::Frequency),
+            "frecency" => Ok(SortOrder::Frecency),
+            "recency" => Ok(SortOrder::Recency),
+            "raw" => Ok(SortOrder::Raw),
+            _ => Err(anyhow!("Unknown sort order {}", string)),
+        }
+    }
+}
+
+pub fn sort<T>(history: Vec<T>, order: SortOrder) -> Vec<T>
+where
+    T: Clone + Eq + Hash + Timestamp,
+{
+    match order {
+        SortOrder::Frequency => by_frequency(history),
+        SortOrder::Frecency => by_frecency(history),
+        SortOrder::Recency => by_recency(history),
+        SortOrder::Raw => history,
+    }
+}
+
+fn by_frequency<T>(history: Vec<T>) -> Vec<T>
 where
     T: Clone + Eq + Hash,
 {
     let freq_map = frequency_map(&history);
+    let mut history = by_recency(history);
+    history.sort_by_key(|c| Reverse(freq_map.get(c).unwrap()));
+    history
+}
+
+fn by_frecency<T>(history: Vec<T>) -> Vec<T>
+where
+    T: Clone + Eq + Hash + Timestamp,
+{
+    let frec_map = frecency_map(&history);
+    let mut history = by_recency(history);
+    history.sort_by(|a, b| frec_map[b].total_cmp(&frec_map[a]));
+    history
+}
+
+fn by_recency<T>(mut history: Vec<T>) -> Vec<T>
+where
+    T: Clone + Eq + Hash,
+{
     let pos_map = position_map(&history);
+    /* Reversed first, so that deduplication keeps the most recent
+     * occurrence of each command along with its metadata. */
+    history.reverse();
     history.sort_by_key(|c| Reverse(pos_map.get(c).unwrap()));
     history.dedup();
-    history.sort_by_key(|c| Reverse(freq_map.get(c).unwrap()));
     history
 }
 
-fn frequency_map<T>(history: &[T]) -> HashMap<T, usize>
+pub fn frequency_map<T>(history: &[T]) -> HashMap<T, usize>
 where
     T: Clone + Eq + Hash,
 {
@@ -25,7 +99,57 @@ where
     map
 }
 
-fn position_map<T>(history: &[T]) -> HashMap<T, usize>
+pub fn metadata_map<T>(history: &[T]) -> HashMap<T, Metadata>
+where
+    T: Clone + Eq + Hash + Timestamp,
+{
+    let freq_map = frequency_map(history);
+    let pos_map = position_map(history);
+    let mut map: HashMap<T, Metadata> = HashMap::new();
+    history.iter().for_each(|cmd| {
+        let metadata = map.entry(cmd.to_owned()).or_insert(Metadata {
+            count: freq_map[cmd],
+            last_position: pos_map[cmd],
+            first_seen: None,
+            last_seen: None,
+        });
+        if let Some(t) = cmd.timestamp() {
+            metadata.first_seen = Some(metadata.first_seen.map_or(t, |first| first.min(t)));
+            metadata.last_seen = Some(metadata.last_seen.map_or(t, |last| last.max(t)));
+        }
+    });
+    map
+}
+
+fn frecency_map<T>(history: &[T]) -> HashMap<T, f64>
+where
+    T: Clone + Eq + Hash + Timestamp,
+{
+    /* Each occurrence is weighted by how long ago it was run, measured
+     * from the most recent entry. Timestamps are used when every entry
+     * has one, and positions otherwise. */
+    let timestamps: Option<Vec<i64>> = history.iter().map(|cmd| cmd.timestamp()).collect();
+    let ages: Vec<f64> = match timestamps {
+        Some(timestamps) => {
+            let latest = timestamps.iter().max().copied().unwrap_or(0);
+            timestamps
+                .iter()
+                .map(|t| (latest - t) as f64 / HALF_LIFE_SECONDS)
+                .collect()
+        }
+        None => (0..history.len())
+            .map(|pos| (history.len() - 1 - pos) as f64 / HALF_LIFE_ENTRIES)
+            .collect(),
+    };
+
+    let mut map = HashMap::new();
+    history.iter().zip(ages).for_each(|(cmd, age)| {
+        *map.entry(cmd.to_owned()).or_insert(0.0) += 0.5f64.powf(age);
+    });
+    map
+}
+
+pub fn position_map<T>(history: &[T]) -> HashMap<T, usize>
 where
     T: Clone + Eq + Hash,
 {
@@ -38,10 +162,76 @@ where
 
 #[cfg(test)]
 mod tests {
-    #[test]
-    fn sort() {
+    use super::{Metadata, SortOrder, Timestamp};
+    use crate::hstr::Entry;
+    use rstest::rstest;
+
+    impl Timestamp for i32 {
+        fn timestamp(&self) -> Option<i64> {
+            None
+        }
+    }
+
+    #[rstest(
+        order,
+        expected,
+        case(SortOrder::Frequency, vec![3, 4, 5, 2, 6]),
+        case(SortOrder::Recency, vec![3, 5, 4, 2, 6]),
+        case(SortOrder::Raw, vec![3, 2, 4, 6, 2, 4, 3, 3, 4, 5, 6, 3, 2, 4, 5, 5, 3])
+    )]
+    fn sort(order: SortOrder, expected: Vec<i32>) {
         let vec = vec![3, 2, 4, 6, 2, 4, 3, 3, 4, 5, 6, 3, 2, 4, 5, 5, 3];
-        let sorted_vec = super::sort(vec);
-        assert_eq!(sorted_vec, [3, 4, 5, 2, 6]);
+        let sorted_vec = super::sort(vec, order);
+        assert_eq!(sorted_vec, expected);
+    }
+
+    #[test]
+    fn frecency_positions() {
+        let mut vec = vec![1; 300];
+        vec.extend(100..5100);
+        vec.extend([2; 5]);
+        let sorted_vec = super::sort(vec.clone(), SortOrder::Frecency);
+        assert_eq!(sorted_vec[0], 2);
+        let sorted_vec = super::sort(vec, SortOrder::Frequency);
+        assert_eq!(sorted_vec[0], 1);
+    }
+
+    #[test]
+    fn metadata() {
+        let entry = |command: &str, timestamp: Option<i64>| Entry {
+            timestamp,
+            ..Entry::from(command)
+        };
+        let history = vec![
+            entry("make", Some(1330648652)),
+            entry("ls", None),
+            entry("make", None),
+            entry("make", Some(1330648651)),
+        ];
+        let metadata = super::metadata_map(&history);
+        assert_eq!(
+            metadata[&Entry::from("make")],
+            Metadata {
+                count: 3,
+                last_position: 3,
+                first_seen: Some(1330648651),
+                last_seen: Some(1330648652),
+            }
+        );
+        assert_eq!(metadata[&Entry::from("ls")].first_seen, None);
+    }
+
+    #[test]
+    fn frecency_timestamps() {
+        let entry = |command: &str, timestamp: i64| Entry {
+            timestamp: Some(timestamp),
+            ..Entry::from(command)
+        };
+        let mut history: Vec<Entry> = (0..300).map(|i| entry("make", 1670000000 + i)).collect();
+        history.extend((0..5).map(|i| entry("cargo test", 1700000000 + i)));
+        history.push(entry("ls", 1700000005));
+        let sorted = super::sort(history, SortOrder::Frecency);
+        assert_eq!(sorted[0], Entry::from("cargo test"));
+        assert_eq!(sorted[1], Entry::from("ls"));
     }
 }
@@ -1,74 +1,105 @@
-use crate::hstr::{self, History, Shell};
+use crate::favorites::Favorites;
+use crate::hstr::{Entry, History};
+use crate::secrets::Secrets;
+use crate::sort::{self, Metadata, SortOrder};
+use anyhow::anyhow;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use regex::{escape, Regex, RegexBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+use unicode_width::UnicodeWidthChar;
 
 #[derive(Clone)]
 pub struct State {
     pub case_sensitivity: bool,
     pub search_mode: SearchMode,
-    pub shell: Shell,
+    pub sort_order: SortOrder,
+    pub view: View,
     pub query: Query,
+    pub raw_history: History,
     pub history: History,
+    pub metadata: HashMap<Entry, Metadata>,
+    pub histfile: PathBuf,
+    pub favorites: Favorites,
+    pub secrets: Secrets,
     pub search_results: History,
 }
 
 impl State {
-    pub fn new(query: &str) -> anyhow::Result<Self> {
-        let shell = Shell::from_str(setenv::get_shell().get_name())?;
-        let history = match shell {
-            Shell::Bash => hstr::get_bash_history(),
-            Shell::Zsh => hstr::get_zsh_history(),
-        };
-
-        Ok(Self {
+    pub fn new(query: &str, raw_history: History, sort_order: SortOrder) -> Self {
+        let history = sort::sort(raw_history.clone(), sort_order);
+        let metadata = sort::metadata_map(&raw_history);
+        Self {
             case_sensitivity: false,
             search_mode: SearchMode::Exact,
+            sort_order,
+            view: View::History,
             query: Query::new(query),
             search_results: history.clone(),
+            raw_history,
             history,
-            shell,
-        })
+            metadata,
+            histfile: PathBuf::new(),
+            favorites: Favorites::default(),
+            secrets: Secrets::default(),
+        }
     }
 
     pub fn search(&mut self) {
-        match self.search_mode {
+        /* While a regex is being typed, it is often invalid,
+         * and the previous results are kept in the meantime. */
+        let _ = self.try_search();
+    }
+
+    pub fn try_search(&mut self) -> anyhow::Result<()> {
+        let candidates = match self.view {
+            View::History => &self.history,
+            View::Favorites => &self.favorites.entries,
+        };
+        self.search_results = match self.search_mode {
             SearchMode::Exact | SearchMode::Regex => {
-                let search_regex = match self.create_search_regex() {
-                    Some(r) => r,
-                    None => {
-                        return;
-                    }
-                };
-                self.search_results = self.history.clone();
-                self.search_results.retain(|cmd| search_regex.is_match(cmd));
+                let search_regex = self.create_search_regex()?;
+                candidates
+                    .iter()
+                    .filter(|cmd| search_regex.is_match(&cmd.text))
+                    .cloned()
+                    .collect()
             }
             SearchMode::Fuzzy => {
-                let query = self.query.text.clone();
-                if self.case_sensitivity {
-                    let matcher = SkimMatcherV2::default().respect_case();
-                    self.history
-                        .retain(|cmd| matcher.fuzzy_match(cmd, &query).is_some());
+                let matcher = if self.case_sensitivity {
+                    SkimMatcherV2::default().respect_case()
                 } else {
-                    let matcher = SkimMatcherV2::default();
-                    self.search_results = self.history.clone();
-                    self.search_results
-                        .retain(|cmd| matcher.fuzzy_match(cmd, &query).is_some());
-                }
+                    SkimMatcherV2::default()
+                };
+                candidates
+                    .iter()
+                    .filter(|cmd| matcher.fuzzy_match(&cmd.text, &self.query.text).is_some())
+                    .cloned()
+                    .collect()
             }
-        }
+        };
+        Ok(())
     }
 
-    fn create_search_regex(&self) -> Option<Regex> {
+    fn create_search_regex(&self) -> anyhow::Result<Regex> {
         let query = match self.search_mode {
             SearchMode::Regex => self.query.text.clone(),
             SearchMode::Exact => escape(&self.query.text),
             _ => unreachable!(),
         };
-        RegexBuilder::new(&query)
+        Ok(RegexBuilder::new(&query)
             .case_insensitive(!self.case_sensitivity)
-            .build()
-            .ok()
+            .build()?)
+    }
+
+    pub fn remove(&mut self, entry: &Entry) {
+        self.raw_history.retain(|cmd| cmd != entry);
+        self.history.retain(|cmd| cmd != entry);
+        self.search_results.retain(|cmd| cmd != entry);
+        self.metadata.remove(entry);
     }
 
     pub fn toggle_case(&mut self) {
@@ -83,47 +114,206 @@ impl State {
             _ => unreachable!(),
         }
     }
+
+    pub fn toggle_view(&mut self) {
+        self.view = match self.view {
+            View::History => View::Favorites,
+            View::Favorites => View::History,
+        }
+    }
+
+    pub fn toggle_sort_order(&mut self) {
+        self.sort_order = match (self.sort_order as u8 + 1) % 4 {
+            0 => SortOrder::Frequency,
+            1 => SortOrder::Frecency,
+            2 => SortOrder::Recency,
+            3 => SortOrder::Raw,
+            _ => unreachable!(),
+        };
+        self.history = sort::sort(self.raw_history.clone(), self.sort_order);
+    }
 }
 
 #[derive(Clone)]
 pub struct Query {
     pub text: String,
+    /* The cursor position, counted in characters, and the text
+     * last removed by one of the kill commands. */
+    cursor: usize,
+    kill_buffer: String,
 }
 
 impl Query {
     pub fn new(text: &str) -> Self {
         Self {
             text: String::from(text),
+            cursor: text.chars().count(),
+            kill_buffer: String::new(),
         }
     }
 
-    pub fn insert_char(&mut self, cursor_position: usize, ch: char) {
-        /* Inserts a character at the cursor position. */
-        let position = self.byte_position(cursor_position);
+    pub fn cursor_width(&self) -> usize {
+        /* How many columns the text before the cursor takes up,
+         * which differs from the cursor position for wide and
+         * zero-width characters. */
+        self.text
+            .chars()
+            .take(self.cursor)
+            .map(|ch| ch.width().unwrap_or(0))
+            .sum()
+    }
+
+    pub fn insert_char(&mut self, ch: char) {
+        let position = self.byte_position(self.cursor);
         self.text.insert(position, ch);
+        self.cursor += 1;
     }
 
-    pub fn remove_char(&mut self, cursor_position: usize) {
-        /* If there is a character before the cursor, remove it. */
-        if let Some(char_at) = cursor_position.checked_sub(1) {
-            let position = self.byte_position(char_at);
-            self.text.remove(position);
+    pub fn backward_delete_char(&mut self) {
+        if self.cursor > 0 {
+            self.remove(self.cursor - 1..self.cursor);
         }
     }
 
+    pub fn delete_char(&mut self) {
+        if self.cursor < self.len() {
+            self.remove(self.cursor..self.cursor + 1);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.len());
+    }
+
+    pub fn move_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.len();
+    }
+
+    pub fn forward_word(&mut self) {
+        self.cursor = self.word_end(is_word_char);
+    }
+
+    pub fn backward_word(&mut self) {
+        self.cursor = self.word_start(is_word_char);
+    }
+
+    pub fn backward_delete_word(&mut self) {
+        /* Words end at whitespace here, like C-w in the shell. */
+        let start = self.word_start(|ch| !ch.is_whitespace());
+        self.kill(start..self.cursor);
+    }
+
+    pub fn backward_kill_word(&mut self) {
+        let start = self.word_start(is_word_char);
+        self.kill(start..self.cursor);
+    }
+
+    pub fn kill_line(&mut self) {
+        self.kill(self.cursor..self.len());
+    }
+
+    pub fn backward_kill_line(&mut self) {
+        self.kill(0..self.cursor);
+    }
+
+    pub fn yank(&mut self) {
+        let position = self.byte_position(self.cursor);
+        self.text.insert_str(position, &self.kill_buffer);
+        self.cursor += self.kill_buffer.chars().count();
+    }
+
+    fn kill(&mut self, range: Range<usize>) {
+        /* Removes the characters in the range, keeping them to be
+         * yanked back later. Nothing is kept if the range is empty,
+         * so that an accidental kill doesn't lose the last one. */
+        if !range.is_empty() {
+            self.kill_buffer = self.remove(range);
+        }
+    }
+
+    fn remove(&mut self, range: Range<usize>) -> String {
+        /* Removes the characters in the range, leaving the
+         * cursor where they started. */
+        let bytes = self.byte_position(range.start)..self.byte_position(range.end);
+        let removed = self.text[bytes.clone()].to_string();
+        self.text.replace_range(bytes, "");
+        self.cursor = range.start;
+        removed
+    }
+
+    fn word_start(&self, is_word: impl Fn(char) -> bool) -> usize {
+        /* Skips back over anything that isn't part of a word,
+         * then over the word itself. */
+        let chars: Vec<char> = self.text.chars().take(self.cursor).collect();
+        let mut start = chars.len();
+        while start > 0 && !is_word(chars[start - 1]) {
+            start -= 1;
+        }
+        while start > 0 && is_word(chars[start - 1]) {
+            start -= 1;
+        }
+        start
+    }
+
+    fn word_end(&self, is_word: impl Fn(char) -> bool) -> usize {
+        let mut chars = self.text.chars().skip(self.cursor).peekable();
+        let mut end = self.cursor;
+        while chars.next_if(|ch| !is_word(*ch)).is_some() {
+            end += 1;
+        }
+        while chars.next_if(|ch| is_word(*ch)).is_some() {
+            end += 1;
+        }
+        end
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
     fn byte_position(&self, index: usize) -> usize {
         /* Calculates the byte position of a character at a given index. */
         self.text.chars().take(index).map(|ch| ch.len_utf8()).sum()
     }
 }
 
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric()
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum View {
+    History,
+    Favorites,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum SearchMode {
     Exact = 0,
     Regex = 1,
     Fuzzy = 2,
 }
 
+impl SearchMode {
+    pub fn from_str(string: &str) -> anyhow::Result<Self> {
+        match string {
+            "exact" => Ok(SearchMode::Exact),
+            "regex" => Ok(SearchMode::Regex),
+            "fuzzy" => Ok(SearchMode::Fuzzy),
+            _ => Err(anyhow!("Unknown search mode {}", string)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -167,9 +357,7 @@ mod tests {
 
     #[fixture]
     pub fn fake_state(fake_history: History) -> State {
-        let mut state = State::new("").unwrap();
-        state.history = fake_history;
-        state
+        State::new("", fake_history, SortOrder::Raw)
     }
 
     #[rstest(
@@ -196,6 +384,157 @@ mod tests {
         fake_state.case_sensitivity = case_sensitivity;
         fake_state.query = Query::new(query);
         fake_state.search();
+        let expected: History = expected.into_iter().map(Entry::from).collect();
+        assert_eq!(fake_state.search_results, expected);
+    }
+
+    #[rstest]
+    fn remove(mut fake_state: State) {
+        let entry = Entry::from("git add .");
+        fake_state.search();
+        fake_state.remove(&entry);
+        assert!(!fake_state.raw_history.contains(&entry));
+        assert!(!fake_state.history.contains(&entry));
+        assert!(!fake_state.search_results.contains(&entry));
+        assert_eq!(fake_state.history.len(), 25);
+    }
+
+    #[rstest(
+        query,
+        expected,
+        search_mode,
+        case("", vec!["make -j4", "git add ."], SearchMode::Exact),
+        case("add", vec!["git add ."], SearchMode::Exact),
+        case("mk", vec!["make -j4"], SearchMode::Fuzzy)
+    )]
+    fn search_favorites(
+        query: &str,
+        expected: Vec<&str>,
+        search_mode: SearchMode,
+        mut fake_state: State,
+    ) {
+        fake_state.favorites.toggle(&Entry::from("make -j4"));
+        fake_state.favorites.toggle(&Entry::from("git add ."));
+        fake_state.toggle_view();
+        fake_state.search_mode = search_mode;
+        fake_state.query = Query::new(query);
+        fake_state.search();
+        let expected: History = expected.into_iter().map(Entry::from).collect();
         assert_eq!(fake_state.search_results, expected);
     }
+
+    fn query_at(text: &str, cursor: usize) -> Query {
+        let mut query = Query::new(text);
+        query.cursor = cursor;
+        query
+    }
+
+    #[rstest(
+        text,
+        cursor,
+        expected_text,
+        expected_cursor,
+        case("git commit -m", 13, "git commit ", 11),
+        case("git commit  ", 12, "git ", 4),
+        case("git commit", 3, " commit", 0),
+        case("cat žluťoučký.txt", 17, "cat ", 4),
+        case("", 0, "", 0)
+    )]
+    fn backward_delete_word(
+        text: &str,
+        cursor: usize,
+        expected_text: &str,
+        expected_cursor: usize,
+    ) {
+        let mut query = query_at(text, cursor);
+        query.backward_delete_word();
+        assert_eq!(query.text, expected_text);
+        assert_eq!(query.cursor, expected_cursor);
+    }
+
+    #[rstest(
+        text,
+        cursor,
+        expected_text,
+        expected_cursor,
+        case("cat žluťoučký.txt", 17, "cat žluťoučký.", 14),
+        case("cat žluťoučký.txt", 13, "cat .txt", 4),
+        case("ls --all", 8, "ls --", 5)
+    )]
+    fn backward_kill_word(text: &str, cursor: usize, expected_text: &str, expected_cursor: usize) {
+        let mut query = query_at(text, cursor);
+        query.backward_kill_word();
+        assert_eq!(query.text, expected_text);
+        assert_eq!(query.cursor, expected_cursor);
+    }
+
+    #[test]
+    fn word_motion() {
+        let mut query = query_at("echo šampion --now", 0);
+        query.forward_word();
+        assert_eq!(query.cursor, 4);
+        query.forward_word();
+        assert_eq!(query.cursor, 12);
+        query.forward_word();
+        assert_eq!(query.cursor, 18);
+        query.forward_word();
+        assert_eq!(query.cursor, 18);
+        query.backward_word();
+        assert_eq!(query.cursor, 15);
+        query.backward_word();
+        assert_eq!(query.cursor, 5);
+    }
+
+    #[test]
+    fn delete_char() {
+        let mut query = query_at("čaj", 0);
+        query.delete_char();
+        assert_eq!(query.text, "aj");
+        query.move_end();
+        query.delete_char();
+        assert_eq!(query.text, "aj");
+        query.backward_delete_char();
+        assert_eq!(query.text, "a");
+        assert_eq!(query.cursor, 1);
+    }
+
+    #[test]
+    fn kill_and_yank() {
+        let mut query = query_at("grep -r spam .", 8);
+        query.kill_line();
+        assert_eq!(query.text, "grep -r ");
+        query.backward_kill_line();
+        assert_eq!(query.text, "");
+        query.yank();
+        assert_eq!(query.text, "grep -r ");
+        query.move_start();
+        query.kill_line();
+        query.kill_line();
+        query.yank();
+        query.yank();
+        assert_eq!(query.text, "grep -r grep -r ");
+        assert_eq!(query.cursor, 16);
+    }
+
+    #[rstest(
+        text,
+        cursor,
+        expected,
+        case("ls", 2, 2),
+        case("cat 日本.txt", 6, 8),
+        case("e\u{301}cho", 2, 1)
+    )]
+    fn cursor_width(text: &str, cursor: usize, expected: usize) {
+        assert_eq!(query_at(text, cursor).cursor_width(), expected);
+    }
+
+    #[test]
+    fn insert_wide() {
+        let mut query = query_at("cat .txt", 4);
+        query.insert_char('日');
+        query.insert_char('本');
+        assert_eq!(query.text, "cat 日本.txt");
+        assert_eq!(query.cursor, 6);
+        assert_eq!(query.cursor_width(), 8);
+    }
 }
@@ -0,0 +1,270 @@
+use crate::hstr::{Entry, History};
+use crate::secrets::Secrets;
+use crate::sort::{self, SortOrder};
+use crate::ui::pp;
+use serde::Serialize;
+use std::io::{Result, Write};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub total: usize,
+    pub unique: usize,
+    pub duplicate_ratio: f64,
+    pub top_commands: Vec<Count>,
+    pub top_executables: Vec<Count>,
+    /* Indexed by hour and by weekday starting with Monday,
+     * in local time. Only known if the history has timestamps. */
+    pub by_hour: Option<Vec<usize>>,
+    pub by_weekday: Option<Vec<usize>>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Count {
+    pub name: String,
+    pub count: usize,
+}
+
+impl Stats {
+    pub fn new(history: &History, top: usize, secrets: &Secrets) -> Self {
+        /* `history` is the whole history as read,
+         * duplicates included. The commands listed
+         * have their secrets masked. */
+        let freq_map = sort::frequency_map(history);
+        let total = history.len();
+        let unique = freq_map.len();
+
+        let top_commands = sort::sort(history.clone(), SortOrder::Frequency)
+            .into_iter()
+            .take(top)
+            .map(|entry| Count {
+                count: freq_map[&entry],
+                name: secrets.mask(&entry.text),
+            })
+            .collect();
+
+        let executables: History = history
+            .iter()
+            .filter_map(|entry| executable(&entry.text))
+            .map(Entry::from)
+            .collect();
+        let exec_freq_map = sort::frequency_map(&executables);
+        let top_executables = sort::sort(executables, SortOrder::Frequency)
+            .into_iter()
+            .take(top)
+            .map(|entry| Count {
+                count: exec_freq_map[&entry],
+                name: secrets.mask(&entry.text),
+            })
+            .collect();
+
+        let local_times: Vec<(usize, usize)> = history
+            .iter()
+            .filter_map(|entry| entry.timestamp.and_then(local_time))
+            .collect();
+        let (by_hour, by_weekday) = if local_times.is_empty() {
+            (None, None)
+        } else {
+            let mut by_hour = vec![0; 24];
+            let mut by_weekday = vec![0; 7];
+            for (hour, weekday) in local_times {
+                by_hour[hour] += 1;
+                by_weekday[weekday] += 1;
+            }
+            (Some(by_hour), Some(by_weekday))
+        };
+
+        Self {
+            total,
+            unique,
+            duplicate_ratio: match total {
+                0 => 0.0,
+                _ => 1.0 - unique as f64 / total as f64,
+            },
+            top_commands,
+            top_executables,
+            by_hour,
+            by_weekday,
+        }
+    }
+
+    pub fn write_json(&self, writer: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer(&mut *writer, self)?;
+        writer.write_all(b"\n")
+    }
+
+    pub fn write_text(&self, writer: &mut dyn Write) -> Result<()> {
+        writeln!(writer, "Entries          {:>8}", self.total)?;
+        writeln!(writer, "Unique entries   {:>8}", self.unique)?;
+        writeln!(
+            writer,
+            "Duplicate ratio  {:>7.1}%",
+            self.duplicate_ratio * 100.0
+        )?;
+
+        write_counts(writer, "Top commands", "COMMAND", &self.top_commands)?;
+        write_counts(
+            writer,
+            "Top executables",
+            "EXECUTABLE",
+            &self.top_executables,
+        )?;
+
+        if let Some(by_hour) = &self.by_hour {
+            let labels: Vec<String> = (0..24).map(|hour| format!("{:02}", hour)).collect();
+            write_histogram(writer, "Activity by hour", &labels, by_hour)?;
+        }
+        if let Some(by_weekday) = &self.by_weekday {
+            let labels: Vec<String> = WEEKDAYS.iter().map(|day| day.to_string()).collect();
+            write_histogram(writer, "Activity by weekday", &labels, by_weekday)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_counts(
+    writer: &mut dyn Write,
+    title: &str,
+    heading: &str,
+    counts: &[Count],
+) -> Result<()> {
+    writeln!(writer, "\n{}\n", title)?;
+    writeln!(writer, "  {:>8}  {}", "COUNT", heading)?;
+    counts.iter().try_for_each(|count| {
+        writeln!(
+            writer,
+            "  {:>8}  {}",
+            count.count,
+            pp::single_line(&count.name)
+        )
+    })
+}
+
+fn write_histogram(
+    writer: &mut dyn Write,
+    title: &str,
+    labels: &[String],
+    values: &[usize],
+) -> Result<()> {
+    writeln!(writer, "\n{}\n", title)?;
+    let max = values.iter().max().copied().unwrap_or(0).max(1);
+    labels.iter().zip(values).try_for_each(|(label, value)| {
+        let bar = "#".repeat(value * BAR_WIDTH / max);
+        writeln!(writer, "  {:<3}  {:<40}  {}", label, bar, value)
+    })
+}
+
+fn executable(command: &str) -> Option<&str> {
+    /* The first word, skipping any leading variable assignments
+     * as in `LANG=C sort`. */
+    command.split_whitespace().find(|word| !is_assignment(word))
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|ch: char| ch.is_ascii_digit())
+                && name
+                    .chars()
+                    .all(|ch| ch == '_' || ch.is_ascii_alphanumeric())
+        }
+        None => false,
+    }
+}
+
+fn local_time(timestamp: i64) -> Option<(usize, usize)> {
+    /* Returns the hour and the weekday, Monday being 0. */
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+    Some((tm.tm_hour as usize, (tm.tm_wday as usize + 6) % 7))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn fake_history() -> History {
+        vec![
+            "git status",
+            "LANG=C sort -u words",
+            "git status",
+            "cargo test",
+            "git push",
+            "git status",
+        ]
+        .into_iter()
+        .map(Entry::from)
+        .collect()
+    }
+
+    #[test]
+    fn stats() {
+        let stats = Stats::new(&fake_history(), 2, &Secrets::default());
+        assert_eq!(stats.total, 6);
+        assert_eq!(stats.unique, 4);
+        assert!((stats.duplicate_ratio - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            stats.top_commands,
+            vec![
+                Count {
+                    name: "git status".into(),
+                    count: 3
+                },
+                Count {
+                    name: "git push".into(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            stats.top_executables[0],
+            Count {
+                name: "git".into(),
+                count: 4
+            }
+        );
+        assert_eq!(stats.by_hour, None);
+        assert_eq!(stats.by_weekday, None);
+    }
+
+    #[test]
+    fn stats_timestamps() {
+        let mut history = fake_history();
+        history[0].timestamp = Some(1330648651);
+        history[1].timestamp = Some(1330648651 + 3600);
+        let stats = Stats::new(&history, 10, &Secrets::default());
+        assert_eq!(stats.by_hour.unwrap().iter().sum::<usize>(), 2);
+        assert_eq!(stats.by_weekday.unwrap().iter().sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn stats_masked() {
+        let mut history = fake_history();
+        history.push(Entry::from("mysql -u root -phunter2"));
+        let stats = Stats::new(&history, 10, &Secrets::default());
+        assert!(stats
+            .top_commands
+            .iter()
+            .any(|count| count.name == "mysql -u root -p*******"));
+    }
+
+    #[rstest(
+        command,
+        expected,
+        case("git status", Some("git")),
+        case("LANG=C FOO_1=bar sort -u", Some("sort")),
+        case("echo a=b", Some("echo")),
+        case("=foo", Some("=foo")),
+        case("   ", None)
+    )]
+    fn executable(command: &str, expected: Option<&str>) {
+        assert_eq!(super::executable(command), expected);
+    }
+}
@@ -0,0 +1,281 @@
+use anyhow::anyhow;
+use ncurses as nc;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::env;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    Default,
+    Light,
+    Monochrome,
+    HighContrast,
+}
+
+impl Theme {
+    fn palette(self) -> Option<Palette> {
+        /* Monochrome uses attributes only, so it has no colors. */
+        let pair = ColorPair::new;
+        match self {
+            Theme::Default => Some(Palette {
+                normal: pair(Color::Default, Color::Default),
+                selected: pair(Color::Black, Color::Green),
+                status: pair(Color::Black, Color::White),
+                matched: pair(Color::Red, Color::Default),
+                error: pair(Color::White, Color::Red),
+            }),
+            Theme::Light => Some(Palette {
+                normal: pair(Color::Default, Color::Default),
+                selected: pair(Color::White, Color::Blue),
+                status: pair(Color::White, Color::Black),
+                matched: pair(Color::Magenta, Color::Default),
+                error: pair(Color::White, Color::Red),
+            }),
+            Theme::HighContrast => Some(Palette {
+                normal: pair(Color::White, Color::Black),
+                selected: pair(Color::Black, Color::Yellow),
+                status: pair(Color::Black, Color::White),
+                matched: pair(Color::Yellow, Color::Black),
+                error: pair(Color::White, Color::Red),
+            }),
+            Theme::Monochrome => None,
+        }
+    }
+}
+
+/* Colors set in the config file, each of which replaces
+ * the color of the theme for that role. */
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub normal: Option<ColorPair>,
+    pub selected: Option<ColorPair>,
+    pub status: Option<ColorPair>,
+    #[serde(rename = "match")]
+    pub matched: Option<ColorPair>,
+    pub error: Option<ColorPair>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Palette {
+    pub normal: ColorPair,
+    pub selected: ColorPair,
+    pub status: ColorPair,
+    pub matched: ColorPair,
+    pub error: ColorPair,
+}
+
+pub fn palette(theme: Theme, colors: &Colors) -> Option<Palette> {
+    /* NO_COLOR, if set to anything, turns colors off
+     * whatever the theme and the config file say. */
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return None;
+    }
+    theme.palette().map(|palette| palette.with(colors))
+}
+
+impl Palette {
+    fn with(self, colors: &Colors) -> Self {
+        Self {
+            normal: colors.normal.unwrap_or(self.normal),
+            selected: colors.selected.unwrap_or(self.selected),
+            status: colors.status.unwrap_or(self.status),
+            matched: colors.matched.unwrap_or(self.matched),
+            error: colors.error.unwrap_or(self.error),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ColorPair {
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl ColorPair {
+    pub fn new(fg: Color, bg: Color) -> Self {
+        Self { fg, bg }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "ColorValue")]
+pub enum Color {
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorValue {
+    Index(u8),
+    Name(String),
+}
+
+impl TryFrom<ColorValue> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ColorValue) -> anyhow::Result<Self> {
+        /* Colors are written as one of the eight basic colors or
+         * `default`, as an index into the 256-color palette, or
+         * as `#rrggbb`. */
+        let name = match value {
+            ColorValue::Index(index) => return Ok(Color::Indexed(index)),
+            ColorValue::Name(name) => name,
+        };
+        let color = match name.as_str() {
+            "default" => Color::Default,
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "white" => Color::White,
+            _ => match name.strip_prefix('#') {
+                Some(hex) if hex.len() == 6 && hex.chars().all(|ch| ch.is_ascii_hexdigit()) => {
+                    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+                    Color::Rgb(channel(0), channel(2), channel(4))
+                }
+                _ => return Err(anyhow!("Invalid color {:?}", name)),
+            },
+        };
+        Ok(color)
+    }
+}
+
+impl Color {
+    pub fn to_curses(self) -> i16 {
+        /* Colors the terminal doesn't have are replaced with the
+         * closest one it does have. */
+        self.to_index(nc::COLORS())
+    }
+
+    fn to_index(self, colors: i32) -> i16 {
+        match self {
+            Color::Default => -1,
+            Color::Black => nc::COLOR_BLACK,
+            Color::Red => nc::COLOR_RED,
+            Color::Green => nc::COLOR_GREEN,
+            Color::Yellow => nc::COLOR_YELLOW,
+            Color::Blue => nc::COLOR_BLUE,
+            Color::Magenta => nc::COLOR_MAGENTA,
+            Color::Cyan => nc::COLOR_CYAN,
+            Color::White => nc::COLOR_WHITE,
+            Color::Indexed(index) if i32::from(index) < colors => i16::from(index),
+            Color::Indexed(index) => nearest(to_rgb(index), 0..8),
+            Color::Rgb(r, g, b) if colors >= 256 => nearest((r, g, b), 16..=255),
+            Color::Rgb(r, g, b) => nearest((r, g, b), 0..8),
+        }
+    }
+}
+
+fn nearest(rgb: (u8, u8, u8), indices: impl Iterator<Item = u8>) -> i16 {
+    let distance = |index: u8| {
+        let (r, g, b) = to_rgb(index);
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+    indices.min_by_key(|index| distance(*index)).unwrap_or(0) as i16
+}
+
+fn to_rgb(index: u8) -> (u8, u8, u8) {
+    /* The xterm 256-color palette: 16 basic colors, a 6x6x6
+     * color cube and 24 shades of gray. */
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=15 => BASIC[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            (
+                LEVELS[(i / 36) as usize],
+                LEVELS[(i / 6 % 6) as usize],
+                LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        value,
+        expected,
+        case(ColorValue::Name("cyan".into()), Color::Cyan),
+        case(ColorValue::Name("#FF8700".into()), Color::Rgb(255, 135, 0)),
+        case(ColorValue::Index(208), Color::Indexed(208))
+    )]
+    fn color(value: ColorValue, expected: Color) {
+        assert_eq!(Color::try_from(value).unwrap(), expected);
+    }
+
+    #[rstest(name, case("orange"), case("#ff870"), case("#gg8700"))]
+    fn invalid_color(name: &str) {
+        assert!(Color::try_from(ColorValue::Name(name.into())).is_err());
+    }
+
+    #[rstest(
+        color,
+        colors,
+        expected,
+        case(Color::Indexed(208), 256, 208),
+        case(Color::Indexed(208), 8, 3),
+        case(Color::Rgb(255, 135, 0), 256, 208),
+        case(Color::Rgb(0, 0, 250), 8, 4),
+        case(Color::Rgb(128, 128, 128), 256, 244),
+        case(Color::Default, 256, -1)
+    )]
+    fn to_index(color: Color, colors: i32, expected: i16) {
+        assert_eq!(color.to_index(colors), expected);
+    }
+
+    #[test]
+    fn overrides() {
+        let colors = Colors {
+            matched: Some(ColorPair::new(Color::Indexed(33), Color::Default)),
+            ..Colors::default()
+        };
+        let light = Theme::Light.palette().unwrap();
+        let palette = light.with(&colors);
+        assert_eq!(palette.matched.fg, Color::Indexed(33));
+        assert_eq!(palette.selected, light.selected);
+        assert_eq!(Theme::Monochrome.palette(), None);
+    }
+}
@@ -1,60 +1,195 @@
-use crate::state::{SearchMode, State};
+use crate::hstr::{Entry, History};
+use crate::keys::{Action, Bindings};
+use crate::state::{Query, SearchMode, State};
+use anyhow::anyhow;
+use curses::{style, Role};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use ncurses as nc;
 use pp::*;
 use regex::Regex;
-use unicode_width::UnicodeWidthChar;
+use serde::Deserialize;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scrolling {
+    Smooth,
+    Page,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Join {
+    And,
+    Semicolon,
+    Newline,
+}
+
+impl Join {
+    pub fn from_str(string: &str) -> anyhow::Result<Self> {
+        match string {
+            "and" => Ok(Join::And),
+            "semicolon" => Ok(Join::Semicolon),
+            "newline" => Ok(Join::Newline),
+            _ => Err(anyhow!("Unknown join {}", string)),
+        }
+    }
 
-const LABEL: &str =
-    "Type to filter, UP/DOWN move, LEFT/RIGHT move cursor, ENTER/TAB select, ESC quit";
+    pub fn join(self, entries: &[Entry]) -> Vec<u8> {
+        let separator: &[u8] = match self {
+            Join::And => b" && ",
+            Join::Semicolon => b"; ",
+            Join::Newline => b"\n",
+        };
+        entries
+            .iter()
+            .map(|entry| entry.bytes.as_slice())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
 
 pub struct UserInterface {
-    cursor_position: usize,
-    page_count: usize,
-    page: usize,
+    /* Both are indices into the search results: the highlighted
+     * entry, and the first entry shown on the screen. */
     highlighted: usize,
+    offset: usize,
+    pub scrolling: Scrolling,
+    pub columns: bool,
+    pub preview: bool,
+    /* Entries marked for output, in the order they were marked. */
+    pub marked: History,
+    pub keys: Bindings,
     pub state: State,
 }
 
 impl UserInterface {
-    pub fn new(query: &str) -> anyhow::Result<Self> {
-        Ok(Self {
-            cursor_position: 0,
-            page_count: 0,
-            page: 1,
+    pub fn new(state: State) -> Self {
+        Self {
             highlighted: 0,
-            state: State::new(query)?,
-        })
+            offset: 0,
+            scrolling: Scrolling::Smooth,
+            columns: false,
+            preview: false,
+            marked: Vec::new(),
+            keys: Bindings::default(),
+            state,
+        }
     }
 
     pub fn populate_screen(&self) {
-        for (row_idx, cmd) in self.get_page_contents().iter().enumerate() {
+        let page_contents = self.get_page_contents();
+        let timestamps =
+            self.columns && self.state.raw_history.iter().any(|e| e.timestamp.is_some());
+        for (row_idx, entry) in page_contents.iter().enumerate() {
+            let cmd = &single_line(&self.state.secrets.mask(&entry.text));
+            /* The metadata columns, if shown, come before the
+             * command and shift it to the right. */
+            let gutter = match self.columns {
+                true => columns(
+                    self.state.favorites.contains(entry),
+                    self.state.metadata.get(entry),
+                    timestamps,
+                ),
+                false => String::new(),
+            };
+            let row = format!("{}{}", gutter, cmd);
             // Make command fit the screen and print everything normally first
-            nc::mvaddstr(row_idx as i32 + 3, 1, &ljust(cmd));
+            nc::mvaddstr(row_idx as i32 + 3, 1, &ljust(&row));
 
             // Paint matched chars, if any;
-            match self.state.search_mode {
-                SearchMode::Exact | SearchMode::Regex => {
-                    let matches = self.substring_indices(cmd, &self.state.query.text);
-
-                    if !matches.is_empty() {
-                        self.paint_matched_chars(cmd, matches, row_idx);
-                    }
-                }
-                SearchMode::Fuzzy => {
-                    let matcher = SkimMatcherV2::default();
-
-                    if let Some(matches) = matcher.fuzzy_indices(cmd, &self.state.query.text) {
-                        self.paint_matched_chars(cmd, matches.1, row_idx);
-                    }
-                }
+            let offset = gutter.width();
+            let matches = self.match_indices(cmd);
+            if !matches.is_empty() {
+                self.paint_matched_chars(cmd, matches, row_idx, offset);
             }
             // Finally, paint selection
-            self.paint_highlighted(cmd, row_idx);
+            self.paint_highlighted(&row, row_idx);
+
+            /* Marked entries get a marker in the otherwise
+             * empty first column. */
+            let marker = match self.marked.contains(entry) {
+                true => "*",
+                false => " ",
+            };
+            nc::attron(style(Role::Match));
+            nc::mvaddstr(row_idx as i32 + 3, 0, marker);
+            nc::attroff(style(Role::Match));
+        }
+        /* Clear the rows left over from a longer page. */
+        nc::mv(page_contents.len() as i32 + 3, 0);
+        nc::clrtobot();
+        if self.preview {
+            self.paint_preview();
         }
         self.paint_bars();
     }
 
+    fn paint_preview(&self) {
+        /* Shows the whole highlighted command, wrapped to the width
+         * of the screen, below a row with what is known about it. */
+        let entry = match self.compute_highlighted() {
+            Some(entry) => entry,
+            None => return,
+        };
+        let top = nc::LINES() - self.preview_height() as i32;
+        let details = preview_details(
+            self.state.metadata.get(&entry),
+            self.state.favorites.contains(&entry),
+            &self.state.histfile.display().to_string(),
+        );
+        nc::attron(style(Role::Status));
+        nc::mvaddstr(top, 1, &ljust(&details));
+        nc::attroff(style(Role::Status));
+
+        let text = self.state.secrets.mask(&entry.text);
+        let matches = self.match_indices(&text);
+        let width = (nc::COLS() as usize).saturating_sub(2);
+        let rows = self.preview_height() - 1;
+        let lines = wrap(&text, width);
+        for (row_idx, line) in lines.iter().take(rows).enumerate() {
+            let row = top + 1 + row_idx as i32;
+            let mut col = 1;
+            for &(byte_idx, ch) in line {
+                let attr = match matches.contains(&byte_idx) {
+                    true => style(Role::Match),
+                    false => 0,
+                };
+                nc::attron(attr);
+                nc::mvaddstr(row, col, &ch.to_string());
+                nc::attroff(attr);
+                col += ch.width().unwrap_or(0) as i32;
+            }
+            /* Mark that the command goes on beyond the preview. */
+            if row_idx + 1 == rows && lines.len() > rows {
+                nc::mvaddstr(row, col.min(width as i32), "…");
+            }
+        }
+    }
+
+    fn match_indices(&self, text: &str) -> Vec<usize> {
+        /* The byte indices of the characters in the text
+         * that match the query. */
+        match self.state.search_mode {
+            SearchMode::Exact | SearchMode::Regex => {
+                self.substring_indices(text, &self.state.query.text)
+            }
+            SearchMode::Fuzzy => {
+                /* fuzzy_indices() counts characters rather than bytes. */
+                let chars: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+                SkimMatcherV2::default()
+                    .fuzzy_indices(text, &self.state.query.text)
+                    .map(|(_, indices)| {
+                        indices
+                            .iter()
+                            .filter_map(|i| chars.get(*i).copied())
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            }
+        }
+    }
+
     fn substring_indices<'a>(&self, string: &'a str, substring: &'a str) -> Vec<usize> {
         // Returns the indices of a substring within a string
         match Regex::new(substring) {
@@ -63,227 +198,347 @@ impl UserInterface {
         }
     }
 
-    fn paint_matched_chars(&self, command: &str, indices: Vec<usize>, row_idx: usize) {
+    fn paint_matched_chars(
+        &self,
+        command: &str,
+        indices: Vec<usize>,
+        row_idx: usize,
+        offset: usize,
+    ) {
+        /* `offset` is the width of whatever is shown before the command. */
+        let width = (nc::COLS() as usize).saturating_sub(2);
         for (col_idx, byte_idx, ch) in column_indices(command) {
+            let col_idx = col_idx + offset;
+            if col_idx + ch.width().unwrap_or(0) > width {
+                break;
+            }
             if indices.contains(&byte_idx) {
-                nc::attron(nc::COLOR_PAIR(5) | nc::A_BOLD());
+                nc::attron(style(Role::Match));
                 nc::mvaddstr(row_idx as i32 + 3, col_idx as i32 + 1, &ch.to_string());
-                nc::attroff(nc::COLOR_PAIR(5) | nc::A_BOLD());
+                nc::attroff(style(Role::Match));
             }
         }
     }
 
     fn paint_highlighted(&self, entry: &str, index: usize) {
-        if index == self.highlighted {
-            nc::attron(nc::COLOR_PAIR(2));
+        if self.offset + index == self.highlighted {
+            nc::attron(style(Role::Selected));
             nc::mvaddstr(index as i32 + 3, 1, &ljust(entry));
-            nc::attroff(nc::COLOR_PAIR(2));
+            nc::attroff(style(Role::Selected));
         }
     }
 
     fn paint_bars(&self) {
-        nc::mvaddstr(1, 1, &ljust(LABEL));
-        nc::attron(nc::COLOR_PAIR(3));
+        nc::mvaddstr(1, 1, &ljust(&self.label()));
+        nc::attron(style(Role::Status));
         nc::mvaddstr(2, 1, &ljust(&self.status_bar()));
-        nc::attroff(nc::COLOR_PAIR(3));
+        nc::attroff(style(Role::Status));
         nc::mvaddstr(0, 1, &top_bar(&self.state.query.text));
+        self.paint_cursor();
+    }
+
+    fn paint_prompt(&self, text: &str) {
+        nc::attron(style(Role::Error));
+        nc::mvaddstr(2, 1, &ljust(&single_line(text)));
+        nc::attroff(style(Role::Error));
+    }
+
+    pub fn confirm(&self, question: &str) -> bool {
+        /* Asks a yes/no question in place of the status bar. */
+        self.paint_prompt(&format!("{} (y/n)", question));
+        matches!(nc::get_wch(), Some(nc::WchResult::Char(ch)) if ch == 'y' as u32)
+    }
+
+    pub fn show_error(&self, message: &str) {
+        /* Shown in place of the status bar until the screen is repainted. */
+        self.paint_prompt(message);
+    }
+
+    fn label(&self) -> String {
+        /* Lists the keys bound to the most common actions,
+         * leaving out the actions that have no key. */
+        let actions: [(&[Action], &str); 6] = [
+            (&[Action::MoveUp, Action::MoveDown], "move"),
+            (&[Action::CursorLeft, Action::CursorRight], "move cursor"),
+            (&[Action::Execute, Action::Edit], "select"),
+            (&[Action::ToggleFavorite], "favorite"),
+            (&[Action::DeleteEntry], "delete"),
+            (&[Action::Quit], "quit"),
+        ];
+        let mut label = String::from("Type to filter");
+        for (actions, description) in actions.iter() {
+            let keys: Vec<String> = actions
+                .iter()
+                .filter_map(|action| self.keys.key(*action))
+                .map(|key| key.to_string())
+                .collect();
+            if !keys.is_empty() {
+                label.push_str(&format!(", {} {}", keys.join("/"), description));
+            }
+        }
+        label
+    }
+
+    fn hint(&self, action: Action) -> String {
+        match self.keys.key(action) {
+            Some(key) => format!(" ({})", key),
+            None => String::new(),
+        }
     }
 
     pub fn status_bar(&self) -> String {
-        format!(
-            "- search:{} (C-e) - case:{} (C-t) - page {}/{} -",
+        let mut status = format!(
+            "- view:{}{} - search:{}{} - case:{}{} - sort:{}{} - {} -",
+            view(self.state.view),
+            self.hint(Action::ToggleView),
             search_mode(self.state.search_mode),
+            self.hint(Action::ToggleSearchMode),
             case(self.state.case_sensitivity),
-            self.current_page(),
-            self.compute_page_count(),
-        )
+            self.hint(Action::ToggleCase),
+            sort_order(self.state.sort_order),
+            self.hint(Action::ToggleSortOrder),
+            self.position(),
+        );
+        if !self.marked.is_empty() {
+            status.push_str(&format!(" marked:{} -", self.marked.len()));
+        }
+        if let Some(metadata) = self.compute_highlighted().and_then(|e| metadata(&e)) {
+            status.push_str(&format!(" {} -", metadata));
+        }
+        status
     }
 
-    pub fn compute_page_count(&self) -> usize {
-        self.state
-            .search_results
-            .chunks(nc::LINES() as usize - 3)
-            .len()
+    fn position(&self) -> String {
+        /* Where the highlighted entry is, as a page
+         * when paging and as an entry when scrolling. */
+        let total = self.state.search_results.len();
+        match (self.scrolling, total) {
+            (_, 0) => "0/0".to_string(),
+            (Scrolling::Page, _) => format!(
+                "page {}/{}",
+                self.offset / self.page_size() + 1,
+                self.compute_page_count()
+            ),
+            (Scrolling::Smooth, _) => format!("{}/{}", self.highlighted + 1, total),
+        }
     }
 
-    fn current_page(&self) -> usize {
-        if self.page_count > 0 {
-            self.page
-        } else {
-            0
-        }
+    pub fn compute_page_count(&self) -> usize {
+        self.state.search_results.len().div_ceil(self.page_size())
     }
 
-    fn compute_page_size(&self) -> usize {
-        self.get_page_contents().len()
+    fn page_size(&self) -> usize {
+        /* Rows below the top bar, the label and the status bar,
+         * and above the preview. */
+        (nc::LINES() - 3 - self.preview_height() as i32).max(1) as usize
     }
 
-    fn get_page_contents(&self) -> Vec<String> {
-        match self
-            .state
-            .search_results
-            .chunks(nc::LINES() as usize - 3)
-            .nth(self.page - 1)
-        {
-            Some(cmds) => cmds.to_vec(),
-            None => Vec::new(),
+    fn preview_height(&self) -> usize {
+        /* The preview takes up the bottom third of the screen:
+         * a row with the metadata and the rows for the command. */
+        match self.preview {
+            true => (nc::LINES() / 3).max(2) as usize,
+            false => 0,
         }
     }
 
-    pub fn compute_highlighted(&self) -> Option<String> {
-        self.get_page_contents().get(self.highlighted).cloned()
+    fn get_page_contents(&self) -> &[Entry] {
+        let results = &self.state.search_results;
+        let start = self.offset.min(results.len());
+        let end = (self.offset + self.page_size()).min(results.len());
+        &results[start..end]
     }
 
-    pub fn turn_page(&mut self, direction: Direction) {
-        /* Turning the page essentially works as follows:
-         *
-         * We are getting the potential page by subtracting 1
-         * from the page number, because pages are 1-based, and
-         * we need them to be 0-based for the calculation to work.
-         * Then we add the direction which is always +1 or -1.
-         *
-         * We then use the remainder part of Euclidean division of
-         * potential page over total number of pages, in order to
-         * wrap the page number around the total number of pages.
-         *
-         * This means that if we are on page 4, and there are 4 pages in total,
-         * the command to go to the next page would result in rem(4, 4),
-         * which is 0, and by adjusting the page number to be 1-based,
-         * we get back to page 1, as desired.
-         *
-         * This also works in the opposite direction:
-         *
-         * If there are 4 total pages, and we are on page 1, and we issue
-         * the command to go to the previous page, we are doing: rem(-1, 4),
-         * which is 3. By adjusting the page number to be 1-based,
-         * we get to the 4th page.
-         *
-         * The total number of pages being 0, which is the case when there
-         * are no entries in the history, means that we are dividing by 0,
-         * which is undefined, and rem() returns None, which means that we are
-         * on page 1.
-         */
-        nc::clear();
-        let potential_page = (self.page - 1) as isize + (direction as isize);
-        self.page = match potential_page.checked_rem_euclid(self.compute_page_count() as isize) {
-            Some(x) => (x + 1) as usize,
-            None => 1,
-        }
+    pub fn compute_highlighted(&self) -> Option<Entry> {
+        self.state.search_results.get(self.highlighted).cloned()
     }
 
     pub fn move_highlighted(&mut self, direction: Direction) {
-        /* Moving the highlighted entry works as follows:
-         *
-         * We are getting the potential highlighted entry
-         * index by adding the direction to the current
-         * highlighted entry index. Then, we do a checked
-         * Euclidian division of potential highlighted entry
-         * index over total number of entries on the current
-         * page. Specifically, we are interested in the
-         * remainder part:
-         *
-         * If the remainder is zero, and the direction is
-         * Direction::Forward, this means that the potential
-         * highlighted entry is on the next page and we need to
-         * turn the page forward.
-         *
-         * If the remainder is equal to the number of entries
-         * on a page minus one (adjusting for `self.highlighted`
-         * being 0-based), and the direction is Direction::Backward,
-         * this means the potential highlighted entry is on the
-         * previous page, and we need to turn the page backwards.
-         */
-        let potential_highlighted = self.highlighted as isize + direction as isize;
-        if let Some(rem) =
-            potential_highlighted.checked_rem_euclid(self.compute_page_size() as isize)
-        {
-            self.highlighted = rem as usize;
-            match direction {
-                Direction::Forward => {
-                    if self.highlighted == 0 {
-                        self.turn_page(Direction::Forward);
-                    }
-                }
-                Direction::Backward => {
-                    /* -1 because `self.highlighted` is 0-based. */
-                    if self.highlighted == self.compute_page_size() - 1 {
-                        self.turn_page(Direction::Backward);
-
-                        /* Because we might end up on a page that
-                         * has fewer entries than the one used in
-                         * the calculation, we need to select the
-                         * last entry again - now based on the count
-                         * of entries of the newly selected page. */
-                        self.highlighted = self.compute_page_size() - 1;
-                    }
+        /* Moving past the last entry wraps around to the
+         * first one, and the other way around. */
+        let total = self.state.search_results.len() as isize;
+        if total == 0 {
+            return;
+        }
+        self.highlighted =
+            (self.highlighted as isize + direction as isize).rem_euclid(total) as usize;
+        self.scroll_into_view();
+    }
+
+    pub fn turn_page(&mut self, direction: Direction) {
+        /* When paging, turning the page past the last one wraps
+         * around to the first one and keeps the highlighted row,
+         * as far as the new page has enough entries. When
+         * scrolling, the highlighted entry and the screen move
+         * by a page and stop at either end. */
+        let page_size = self.page_size();
+        match self.scrolling {
+            Scrolling::Page => {
+                let page_count = self.compute_page_count() as isize;
+                if page_count == 0 {
+                    return;
                 }
+                let page = (self.offset / page_size) as isize + direction as isize;
+                let row = self.highlighted - self.offset;
+                self.offset = page.rem_euclid(page_count) as usize * page_size;
+                self.highlighted = self.offset + row;
+                self.fit_highlighted();
             }
+            Scrolling::Smooth => self.move_by(direction as isize * page_size as isize),
         }
     }
 
-    pub fn set_highlighted(&mut self, i: usize) {
-        self.highlighted = i;
+    pub fn move_half_page(&mut self, direction: Direction) {
+        self.move_by(direction as isize * (self.page_size() / 2).max(1) as isize);
     }
 
-    pub fn set_page(&mut self, i: usize) {
-        self.page = i;
+    pub fn move_to_first(&mut self) {
+        self.move_by(-(self.state.search_results.len() as isize));
     }
 
-    pub fn move_cursor(&mut self, direction: Direction) {
-        match direction {
-            Direction::Forward => {
-                if self.cursor_position < self.state.query.text.chars().count() {
-                    self.cursor_position += 1;
-                }
-            }
-            Direction::Backward => {
-                if self.cursor_position > 0 {
-                    self.cursor_position -= 1;
-                }
+    pub fn move_to_last(&mut self) {
+        self.move_by(self.state.search_results.len() as isize);
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        /* Moves the highlighted entry and the screen together,
+         * so that the entry stays on the same row if it can. */
+        let last = self.state.search_results.len().saturating_sub(1) as isize;
+        self.highlighted = (self.highlighted as isize + delta).max(0).min(last) as usize;
+        self.offset = (self.offset as isize + delta).max(0) as usize;
+        self.scroll_into_view();
+    }
+
+    fn scroll_into_view(&mut self) {
+        self.offset = fit_offset(
+            self.offset,
+            self.highlighted,
+            self.state.search_results.len(),
+            self.page_size(),
+            self.scrolling,
+        );
+    }
+
+    pub fn toggle_mark(&mut self) {
+        if let Some(entry) = self.compute_highlighted() {
+            match self.marked.contains(&entry) {
+                true => self.marked.retain(|marked| *marked != entry),
+                false => self.marked.push(entry),
             }
         }
+    }
 
-        let prompt_length = pp::get_shell_prompt().chars().count();
-        let query_width: usize = self
-            .state
-            .query
-            .text
-            .chars()
-            .take(self.cursor_position)
-            .map(|ch| ch.width().unwrap_or(0))
-            .sum();
+    pub fn compute_selection(&self, join: Join) -> Option<Vec<u8>> {
+        /* The marked entries if there are any, otherwise
+         * the highlighted one. */
+        match self.marked.is_empty() {
+            true => self.compute_highlighted().map(|entry| entry.bytes),
+            false => Some(join.join(&self.marked)),
+        }
+    }
+
+    pub fn remove(&mut self, entry: &Entry) {
+        self.marked.retain(|marked| marked != entry);
+        self.state.remove(entry);
+        self.fit_highlighted();
+    }
+
+    pub fn fit_highlighted(&mut self) {
+        /* Entries may have been removed from the search results,
+         * possibly the highlighted one or the ones after it. */
+        self.highlighted = self
+            .highlighted
+            .min(self.state.search_results.len().saturating_sub(1));
+        self.scroll_into_view();
+    }
+
+    pub fn reset_highlighted(&mut self) {
+        self.highlighted = 0;
+        self.offset = 0;
+    }
+
+    pub fn edit_query(&mut self, edit: impl FnOnce(&mut Query)) {
+        /* Searches again if the edit changed the query, rather
+         * than only moving the cursor. */
+        let text = self.state.query.text.clone();
+        edit(&mut self.state.query);
+        if self.state.query.text != text {
+            self.reset_highlighted();
+            nc::clear();
+            self.state.search();
+            self.populate_screen();
+        }
+        self.paint_cursor();
+    }
 
+    fn paint_cursor(&self) {
+        let prompt_length = pp::get_shell_prompt().chars().count();
         nc::wmove(
             nc::stdscr(),
             0,
-            (prompt_length + 1 + 1 + query_width) as i32,
+            (prompt_length + 1 + 1 + self.state.query.cursor_width()) as i32,
         );
     }
-
-    pub fn get_cursor_position(&self) -> usize {
-        self.cursor_position
-    }
 }
 
 pub mod curses {
+    use crate::keys::ESC_DELAY;
+    use crate::theme::{ColorPair, Palette};
     use ncurses as nc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /* Set when the screen is drawn with attributes only. */
+    static MONOCHROME: AtomicBool = AtomicBool::new(false);
 
-    pub fn init() {
+    #[derive(Clone, Copy)]
+    pub enum Role {
+        Selected = 2,
+        Status = 3,
+        Match = 5,
+        Error = 6,
+    }
+
+    pub fn init(palette: Option<Palette>) {
         nc::setlocale(nc::LcCategory::all, "");
         nc::initscr();
         nc::noecho();
         nc::keypad(nc::stdscr(), true);
-        init_color_pairs();
+        nc::set_escdelay(ESC_DELAY);
+        match palette {
+            Some(palette) if nc::has_colors() => init_color_pairs(palette),
+            _ => MONOCHROME.store(true, Ordering::Relaxed),
+        }
     }
 
-    pub fn init_color_pairs() {
+    pub fn init_color_pairs(palette: Palette) {
         nc::start_color();
         nc::use_default_colors();
-        nc::init_pair(1, nc::COLOR_WHITE, nc::COLOR_BLACK); // normal
-        nc::init_pair(2, nc::COLOR_BLACK, nc::COLOR_GREEN); // highlighted-green (highlighted item)
-        nc::init_pair(3, nc::COLOR_BLACK, nc::COLOR_WHITE); // highlighted-white (status)
-        nc::init_pair(5, nc::COLOR_RED, -1); // red (searched items)
-        nc::init_pair(6, nc::COLOR_WHITE, nc::COLOR_RED); // higlighted-red
+        init_pair(1, palette.normal); // normal
+        init_pair(Role::Selected as i16, palette.selected); // highlighted item
+        init_pair(Role::Status as i16, palette.status); // status
+        init_pair(Role::Match as i16, palette.matched); // searched items
+        init_pair(Role::Error as i16, palette.error); // errors and prompts
+        nc::bkgd(' ' as nc::chtype | nc::COLOR_PAIR(1));
+    }
+
+    fn init_pair(index: i16, pair: ColorPair) {
+        nc::init_pair(index, pair.fg.to_curses(), pair.bg.to_curses());
+    }
+
+    pub fn style(role: Role) -> nc::attr_t {
+        /* Without colors, roles are told apart by bold and reverse. */
+        let bold = match role {
+            Role::Match | Role::Error => nc::A_BOLD(),
+            Role::Selected | Role::Status => 0,
+        };
+        if MONOCHROME.load(Ordering::Relaxed) {
+            match role {
+                Role::Match => bold,
+                _ => bold | nc::A_REVERSE(),
+            }
+        } else {
+            bold | nc::COLOR_PAIR(role as i16)
+        }
     }
 
     pub fn teardown() {
@@ -294,12 +549,15 @@ pub mod curses {
     }
 }
 
-mod pp {
+pub mod pp {
     /* Pretty printer */
-    use crate::state::SearchMode;
+    use crate::hstr::Entry;
+    use crate::sort::{Metadata, SortOrder};
+    use crate::state::{SearchMode, View};
     use ncurses as nc;
     use std::env;
-    use unicode_width::UnicodeWidthStr;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use unicode_width::UnicodeWidthChar;
 
     pub fn top_bar(query: &str) -> String {
         format!("{} {}", get_shell_prompt(), query)
@@ -313,6 +571,13 @@ mod pp {
         )
     }
 
+    pub fn view(value: View) -> &'static str {
+        match value {
+            View::History => "history",
+            View::Favorites => "favorites",
+        }
+    }
+
     pub fn search_mode(value: SearchMode) -> &'static str {
         match value {
             SearchMode::Exact => "exact",
@@ -321,6 +586,15 @@ mod pp {
         }
     }
 
+    pub fn sort_order(value: SortOrder) -> &'static str {
+        match value {
+            SortOrder::Frequency => "frequency",
+            SortOrder::Frecency => "frecency",
+            SortOrder::Recency => "recency",
+            SortOrder::Raw => "raw",
+        }
+    }
+
     pub fn case(value: bool) -> &'static str {
         if value {
             "sensitive"
@@ -329,17 +603,154 @@ mod pp {
         }
     }
 
+    pub fn metadata(entry: &Entry) -> Option<String> {
+        match (entry.timestamp, entry.duration) {
+            (Some(t), Some(d)) => Some(format!("{}, took {}", time_ago(t), elapsed(d))),
+            (Some(t), None) => Some(time_ago(t)),
+            (None, Some(d)) => Some(format!("took {}", elapsed(d))),
+            (None, None) => None,
+        }
+    }
+
+    pub fn time_ago(timestamp: i64) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let seconds = now.saturating_sub(timestamp).max(0) as u64;
+        format!("{} ago", largest_unit(seconds))
+    }
+
+    pub fn elapsed(seconds: u64) -> String {
+        match seconds {
+            0..=59 => format!("{}s", seconds),
+            60..=3599 => format!("{}m{}s", seconds / 60, seconds % 60),
+            _ => format!("{}h{}m", seconds / 3600, seconds % 3600 / 60),
+        }
+    }
+
+    fn largest_unit(seconds: u64) -> String {
+        match seconds {
+            0..=59 => format!("{}s", seconds),
+            60..=3599 => format!("{}m", seconds / 60),
+            3600..=86399 => format!("{}h", seconds / 3600),
+            _ => format!("{}d", seconds / 86400),
+        }
+    }
+
+    pub fn single_line(command: &str) -> String {
+        /* Multi-line entries are shown on one row, with a marker
+         * in place of each newline. */
+        command.replace('\n', "↵")
+    }
+
+    pub fn columns(favorite: bool, metadata: Option<&Metadata>, timestamps: bool) -> String {
+        /* A favorite marker, how many times the command was run,
+         * and when it was last run if the history has timestamps.
+         * Entries without metadata, such as favorites that are no
+         * longer in the history, get blank columns. */
+        let mut columns = String::from(if favorite { "★" } else { " " });
+        let count = metadata.map(|m| m.count.to_string()).unwrap_or_default();
+        columns.push_str(&format!(" {:>5}", count));
+        if timestamps {
+            let last_seen = metadata.and_then(|m| m.last_seen).map(time_ago);
+            columns.push_str(&format!(" {:>8}", last_seen.unwrap_or_default()));
+        }
+        columns.push_str("  ");
+        columns
+    }
+
+    pub fn preview_details(metadata: Option<&Metadata>, favorite: bool, histfile: &str) -> String {
+        let mut details = String::from("-");
+        if let Some(metadata) = metadata {
+            details.push_str(&format!(" count:{} -", metadata.count));
+            if let Some(first_seen) = metadata.first_seen {
+                details.push_str(&format!(" first:{} -", time_ago(first_seen)));
+            }
+            if let Some(last_seen) = metadata.last_seen {
+                details.push_str(&format!(" last:{} -", time_ago(last_seen)));
+            }
+        }
+        let source = match (metadata.is_some(), favorite) {
+            (true, true) => format!("{}, favorites", histfile),
+            (true, false) => histfile.to_string(),
+            (false, _) => "favorites".to_string(),
+        };
+        details.push_str(&format!(" source:{} -", source));
+        details
+    }
+
+    pub fn wrap(text: &str, width: usize) -> Vec<Vec<(usize, char)>> {
+        /* Splits the text into rows of at most `width` columns,
+         * starting a new row at each newline. Each character comes
+         * with its byte index in the text. */
+        let mut rows = vec![Vec::new()];
+        let mut used = 0;
+        for (byte_idx, ch) in text.char_indices() {
+            if ch == '\n' {
+                rows.push(Vec::new());
+                used = 0;
+                continue;
+            }
+            let ch_width = ch.width().unwrap_or(0);
+            if used + ch_width > width && used > 0 {
+                rows.push(Vec::new());
+                used = 0;
+            }
+            rows.last_mut().unwrap().push((byte_idx, ch));
+            used += ch_width;
+        }
+        rows
+    }
+
     pub fn ljust(string: &str) -> String {
-        let mut s = string
-            .chars()
-            .take(nc::COLS() as usize - 2)
-            .collect::<String>();
-        let padding = " ".repeat((nc::COLS() as usize).saturating_sub(string.width() + 2));
-        s.push_str(padding.as_str());
+        fit(string, (nc::COLS() as usize).saturating_sub(2))
+    }
+
+    pub fn fit(string: &str, width: usize) -> String {
+        /* Cuts the string or pads it with spaces, so that
+         * it takes up exactly `width` columns. */
+        let mut s = String::new();
+        let mut used = 0;
+        for ch in string.chars() {
+            let ch_width = ch.width().unwrap_or(0);
+            if used + ch_width > width {
+                break;
+            }
+            used += ch_width;
+            s.push(ch);
+        }
+        s.push_str(&" ".repeat(width - used));
         s
     }
 }
 
+fn fit_offset(
+    offset: usize,
+    highlighted: usize,
+    total: usize,
+    page_size: usize,
+    scrolling: Scrolling,
+) -> usize {
+    /* Returns the index of the first entry to show so that the
+     * highlighted one is on the screen. Pages always start at a
+     * multiple of the page size, while scrolling moves the screen
+     * as little as possible and doesn't leave empty rows below
+     * the last entry. */
+    match scrolling {
+        Scrolling::Page => highlighted - highlighted % page_size,
+        Scrolling::Smooth => {
+            let offset = offset.min(total.saturating_sub(page_size));
+            if highlighted < offset {
+                highlighted
+            } else if highlighted >= offset + page_size {
+                highlighted + 1 - page_size
+            } else {
+                offset
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Direction {
     Forward = 1,
@@ -364,7 +775,7 @@ impl Iterator for ColumnIndices<'_> {
     }
 }
 
-pub fn column_indices(s: &str) -> ColumnIndices {
+pub fn column_indices(s: &str) -> ColumnIndices<'_> {
     ColumnIndices {
         inner: s.char_indices(),
         next_col: 0,
@@ -374,8 +785,21 @@ pub fn column_indices(s: &str) -> ColumnIndices {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sort::SortOrder;
     use rstest::rstest;
 
+    #[rstest(
+        seconds,
+        expected,
+        case(0, "0s"),
+        case(59, "59s"),
+        case(125, "2m5s"),
+        case(7322, "2h2m")
+    )]
+    fn elapsed(seconds: u64, expected: &str) {
+        assert_eq!(pp::elapsed(seconds), expected);
+    }
+
     #[rstest(
         string,
         substring,
@@ -385,10 +809,127 @@ mod tests {
         case("ping -c 10 www.google.com", "[0-9]+", vec![8, 9])
     )]
     fn matched_chars_indices(string: &str, substring: &str, expected: Vec<usize>) {
-        let user_interface = UserInterface::new("").unwrap();
+        let user_interface = UserInterface::new(State::new("", Vec::new(), SortOrder::Frequency));
         assert_eq!(
             user_interface.substring_indices(string, substring),
             expected
         );
     }
+
+    #[rstest(
+        offset,
+        highlighted,
+        scrolling,
+        expected,
+        case(0, 4, Scrolling::Smooth, 0),
+        case(0, 5, Scrolling::Smooth, 1),
+        case(6, 3, Scrolling::Smooth, 3),
+        case(8, 9, Scrolling::Smooth, 7),
+        case(0, 12, Scrolling::Smooth, 8),
+        case(0, 5, Scrolling::Page, 5),
+        case(7, 4, Scrolling::Page, 0),
+        case(0, 11, Scrolling::Page, 10)
+    )]
+    fn fit_offset(offset: usize, highlighted: usize, scrolling: Scrolling, expected: usize) {
+        /* 12 entries, 5 of which fit on the screen. */
+        assert_eq!(
+            super::fit_offset(offset, highlighted, 12, 5, scrolling),
+            expected
+        );
+    }
+
+    #[rstest(
+        string,
+        width,
+        expected,
+        case("ls", 4, "ls  "),
+        case("cat spam", 5, "cat s"),
+        case("echo 日本語", 8, "echo 日 "),
+        case("e\u{301}cho", 5, "e\u{301}cho ")
+    )]
+    fn fit(string: &str, width: usize, expected: &str) {
+        assert_eq!(pp::fit(string, width), expected);
+    }
+
+    #[test]
+    fn columns() {
+        let metadata = crate::sort::Metadata {
+            count: 12,
+            last_position: 3,
+            first_seen: None,
+            last_seen: None,
+        };
+        assert_eq!(pp::columns(true, Some(&metadata), false), "★    12  ");
+        assert_eq!(pp::columns(false, None, true), " ".repeat(18));
+    }
+
+    #[test]
+    fn wrap() {
+        let rows = pp::wrap("cat <<EOF\nšpam spam\nEOF", 5);
+        let rows: Vec<String> = rows
+            .iter()
+            .map(|row| row.iter().map(|(_, ch)| ch).collect())
+            .collect();
+        assert_eq!(rows, vec!["cat <", "<EOF", "špam ", "spam", "EOF"]);
+        assert_eq!(pp::wrap("ab日", 3)[1], vec![(2, '日')]);
+    }
+
+    #[test]
+    fn preview_details() {
+        let metadata = crate::sort::Metadata {
+            count: 2,
+            last_position: 3,
+            first_seen: None,
+            last_seen: None,
+        };
+        assert_eq!(
+            pp::preview_details(Some(&metadata), true, "~/.bash_history"),
+            "- count:2 - source:~/.bash_history, favorites -"
+        );
+        assert_eq!(
+            pp::preview_details(None, true, "~/.bash_history"),
+            "- source:favorites -"
+        );
+    }
+
+    #[rstest(
+        join,
+        expected,
+        case(Join::And, "make && make install"),
+        case(Join::Semicolon, "make; make install"),
+        case(Join::Newline, "make\nmake install")
+    )]
+    fn joined(join: Join, expected: &str) {
+        let entries: History = vec!["make", "make install"]
+            .into_iter()
+            .map(Entry::from)
+            .collect();
+        assert_eq!(join.join(&entries), expected.as_bytes());
+    }
+
+    #[test]
+    fn compute_selection() {
+        let history = vec!["make", "make install", "make clean"]
+            .into_iter()
+            .map(Entry::from)
+            .collect();
+        let mut user_interface = UserInterface::new(State::new("", history, SortOrder::Raw));
+        assert_eq!(
+            user_interface.compute_selection(Join::And),
+            Some(b"make".to_vec())
+        );
+        user_interface.highlighted = 2;
+        user_interface.toggle_mark();
+        user_interface.highlighted = 0;
+        user_interface.toggle_mark();
+        assert_eq!(
+            user_interface.compute_selection(Join::And),
+            Some(b"make clean && make".to_vec())
+        );
+        user_interface.toggle_mark();
+        assert_eq!(
+            user_interface.compute_selection(Join::And),
+            Some(b"make clean".to_vec())
+        );
+    }
 }
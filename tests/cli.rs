@@ -0,0 +1,157 @@
+use std::fs;
+use std::process::{Command, Output};
+
+const HISTORY: &str = "\
+#1330648651
+cat spam
+#1330648652
+git add .
+#1330648653
+cat SPAM
+#1330648654
+make -j4
+#1330648655
+cat spam
+#1330648656
+cat <<EOF
+spam
+EOF
+";
+
+fn hstr(name: &str, args: &[&str]) -> Output {
+    hstr_with_env(name, args, &[])
+}
+
+fn hstr_with_env(name: &str, args: &[&str], env: &[(&str, &str)]) -> Output {
+    /* Every test gets its own directory, so that nothing
+     * from the user's environment is picked up. */
+    let dir = std::env::temp_dir().join(format!("hstr-rs-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let histfile = dir.join("bash_history");
+    fs::write(&histfile, HISTORY).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hstr-rs"))
+        .arg("--histfile")
+        .arg(&histfile)
+        .args(args)
+        .env_remove("HISTFILE")
+        .env_remove("BASH")
+        .env_remove("ZSH_NAME")
+        .env("SHELL", "/bin/bash")
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .envs(env.iter().copied())
+        .output()
+        .unwrap();
+
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+#[test]
+fn list() {
+    let output = hstr("list", &["--list"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "cat spam\ncat <<EOF\nspam\nEOF\nmake -j4\ncat SPAM\ngit add .\n"
+    );
+}
+
+#[test]
+fn env_over_histfile() {
+    let histfile = std::env::temp_dir().join(format!(
+        "hstr-rs-env_over_histfile-{}-bash_history",
+        std::process::id()
+    ));
+    fs::write(&histfile, "uptime\n").unwrap();
+    let output = hstr_with_env(
+        "env_over_histfile",
+        &["--list"],
+        &[("HISTFILE", histfile.to_str().unwrap())],
+    );
+    fs::remove_file(&histfile).unwrap();
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "uptime\n");
+}
+
+#[test]
+fn list_null_separated() {
+    let output = hstr(
+        "list_null_separated",
+        &["--list", "--sort", "recency", "-0"],
+    );
+    assert_eq!(
+        stdout(&output),
+        "cat <<EOF\nspam\nEOF\0cat spam\0make -j4\0cat SPAM\0git add .\0"
+    );
+}
+
+#[test]
+fn filter_exact() {
+    let output = hstr("filter_exact", &["--filter", "spam"]);
+    assert_eq!(
+        stdout(&output),
+        "cat spam\ncat <<EOF\nspam\nEOF\ncat SPAM\n"
+    );
+}
+
+#[test]
+fn filter_regex() {
+    let output = hstr(
+        "filter_regex",
+        &["--filter", "^(make|git)", "--mode", "regex"],
+    );
+    assert_eq!(stdout(&output), "make -j4\ngit add .\n");
+}
+
+#[test]
+fn filter_fuzzy() {
+    let output = hstr("filter_fuzzy", &["--filter", "mj4", "--mode", "fuzzy"]);
+    assert_eq!(stdout(&output), "make -j4\n");
+}
+
+#[test]
+fn filter_invalid_regex() {
+    let output = hstr(
+        "filter_invalid_regex",
+        &["--filter", "(make", "--mode", "regex"],
+    );
+    assert!(!output.status.success());
+    assert!(stdout(&output).is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("regex parse error"));
+}
+
+#[test]
+fn list_jsonl() {
+    let output = hstr("list_jsonl", &["--filter", "make", "--format", "jsonl"]);
+    assert_eq!(
+        stdout(&output),
+        "{\"command\":\"make -j4\",\"count\":1,\"last_position\":3,\"first_seen\":1330648654,\"last_seen\":1330648654,\"secret\":false}\n"
+    );
+}
+
+#[test]
+fn stats_json() {
+    let output = hstr("stats_json", &["stats", "--json", "--top", "1"]);
+    let stats = stdout(&output);
+    assert!(stats.starts_with(
+        "{\"total\":6,\"unique\":5,\"duplicate_ratio\":0.16666666666666663,\"top_commands\":[{\"name\":\"cat spam\",\"count\":2}],\"top_executables\":[{\"name\":\"cat\",\"count\":4}],"
+    ));
+}
+
+#[test]
+fn query_like_subcommand() {
+    /* A query close to the name of a subcommand is
+     * not taken for a misspelt one. */
+    let output = hstr("query_like_subcommand", &["--list", "stat"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "cat spam\ncat <<EOF\nspam\nEOF\nmake -j4\ncat SPAM\ngit add .\n"
+    );
+}